@@ -1,18 +1,18 @@
 use std::borrow::BorrowMut;
-use std::sync::atomic::AtomicBool;
-use std::sync::Arc;
 
 use lalrpop_util::lalrpop_mod;
 use rustyline::completion::FilenameCompleter;
 use rustyline::highlight::MatchingBracketHighlighter;
 use rustyline::hint::HistoryHinter;
 use rustyline::{CompletionType, Config, EditMode, Editor, OutputStreamType};
-use signal_hook::consts::SIGINT;
 
+use crate::shell::common::job_control::init_job_control;
+use crate::shell::common::jobs::report_finished_jobs;
+use crate::shell::common::signals::{init_signal_handlers, update_window_size};
 use crate::shell::common::state::{new_state, State};
-use crate::shell::common::types::Cmd;
-use crate::shell::prompt::prompt::{read_input, PromptError};
-use shell::handle_command::{handle_command, CommandStatus};
+use crate::shell::common::types::CmdList;
+use crate::shell::prompt::prompt::{read_continuation, read_input, PromptError};
+use shell::handle_command::{handle_command, CommandStatus, INTERRUPTED_STATUS};
 use shell::parse_command::parse_input;
 use shell::parse_command::ParseError;
 use shell::rl_helper::RLHelper;
@@ -24,17 +24,16 @@ use std::process::exit;
 
 mod shell;
 
-lalrpop_mod!(pub grammar);
-lalrpop_mod!(pub expansions);
-lalrpop_mod!(pub replacements);
-lalrpop_mod!(pub prompt);
+lalrpop_mod!(#[allow(clippy::all)] pub grammar);
+lalrpop_mod!(#[allow(clippy::all)] pub arithmetic);
+lalrpop_mod!(#[allow(clippy::all)] pub prompt);
 
 fn main() {
     let mut state = new_state().expect("vrsh");
 
     let history_file = format!("{}/.vrsh_history", state.home);
 
-    signal_handling();
+    state.job_control = init_job_control();
 
     let config = Config::builder()
         .history_ignore_space(true)
@@ -47,11 +46,11 @@ fn main() {
         completer: FilenameCompleter::new(),
         hinter: HistoryHinter {},
         highlighter: MatchingBracketHighlighter::new(),
-        colored_prompt: "".to_owned(),
     };
 
     let mut rl = Editor::with_config(config);
     rl.set_helper(Some(helper));
+    signal_handling();
 
     rl.load_history(history_file.as_str())
         .unwrap_or_else(|e| println!("vrsh: failed to read history file 📖: {}", e));
@@ -60,25 +59,58 @@ fn main() {
     let init_file = format!("{}/.vrshrc", state.home);
     println!("vrsh: using init file 📄 '{}'", init_file);
     let init_lines = read_or_create_init_file(init_file);
+    let mut pending = String::new();
     for line in init_lines.into_iter() {
-        let cmd = parse_input(line.clone(), &mut state);
-        handle_cmd(cmd, &line, &mut state)
+        pending += &line;
+        let cmd = parse_input(pending.clone());
+        if let Err(ParseError::Incomplete) = cmd {
+            // The command continues on the next line.
+            pending.push('\n');
+            continue;
+        }
+        handle_cmd(cmd, &pending, &mut state);
+        pending.clear();
+    }
+    if !pending.is_empty() {
+        handle_cmd(Err(ParseError::Incomplete), pending.trim_end(), &mut state);
     }
 
     loop {
-        let input = match read_input(rl.borrow_mut(), &mut state) {
+        report_finished_jobs(&mut state.jobs);
+        update_window_size(&mut state);
+        let mut input = match read_input(rl.borrow_mut(), &mut state) {
             Ok(v) => v,
             Err(e) => match e {
                 PromptError::Ignore => continue,
+                PromptError::Interrupted => {
+                    state.exit_status = INTERRUPTED_STATUS;
+                    continue;
+                }
                 _ => {
                     println!("vrsh: failed to read input 🔎: {}", e);
                     continue;
                 }
             },
         };
+        // Keep reading lines until the command is complete.
+        let cmd = loop {
+            let cmd = parse_input(input.clone());
+            if !matches!(cmd, Err(ParseError::Incomplete)) {
+                break Some(cmd);
+            }
+            match read_continuation(rl.borrow_mut(), &mut state) {
+                Ok(line) => input = format!("{}\n{}", input, line),
+                Err(PromptError::Interrupted) => {
+                    state.exit_status = INTERRUPTED_STATUS;
+                    break None;
+                }
+                Err(_) => break Some(cmd),
+            }
+        };
         rl.add_history_entry(input.clone());
-        let cmd = parse_input(input.clone(), &mut state);
-        handle_cmd(cmd, &input, &mut state);
+        if let Some(cmd) = cmd {
+            handle_cmd(cmd, &input, &mut state);
+        }
 
         match rl.save_history(history_file.as_str()) {
             Ok(_) => {}
@@ -90,15 +122,19 @@ fn main() {
     }
 }
 
-fn handle_cmd(cmd: Result<Cmd, ParseError>, line: &str, state: &mut State) {
+fn handle_cmd(cmd: Result<CmdList, ParseError>, line: &str, state: &mut State) {
     // 📜 🔨 😄
     match cmd {
         Ok(command) => match handle_command(command, state) {
             Ok(val) => match val {
-                CommandStatus::Ok => {}
-                CommandStatus::Exit => exit(0),
+                CommandStatus::Exit(code) => exit(code),
+                // `break`, `continue` and `return` only apply within loops and functions.
+                CommandStatus::Ok
+                | CommandStatus::Break(_)
+                | CommandStatus::Continue(_)
+                | CommandStatus::Return(_) => {}
             },
-            Err(e) => println!("vrsh 😇: {}", e),
+            Err(e) => println!("vrsh: {}", e),
         },
         Err(ParseError::InputEmpty) => {}
         Err(ParseError::Comment) => {}
@@ -108,7 +144,7 @@ fn handle_cmd(cmd: Result<Cmd, ParseError>, line: &str, state: &mut State) {
 
 // 🚦
 fn signal_handling() {
-    match signal_hook::flag::register(SIGINT, Arc::new(AtomicBool::new(false))) {
+    match init_signal_handlers() {
         Ok(_) => {}
         Err(e) => {
             println!("failed to setup signal handling 🚦 {}", e)
@@ -118,7 +154,7 @@ fn signal_handling() {
 
 fn read_or_create_init_file(file_path: String) -> Vec<String> {
     let path = Path::new(&file_path);
-    let file = match File::open(&path) {
+    let file = match File::open(path) {
         Ok(v) => v, // 👓
         Err(e) => {
             // 🖌
@@ -139,8 +175,5 @@ fn read_or_create_init_file(file_path: String) -> Vec<String> {
     io::BufReader::new(file)
         .lines()
         .collect::<io::Result<Vec<String>>>()
-        .expect(&format!(
-            "vrsh: failed to read init file {} ❌👓❌",
-            file_path
-        ))
+        .unwrap_or_else(|_| panic!("vrsh: failed to read init file {} ❌👓❌", file_path))
 }
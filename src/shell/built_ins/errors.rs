@@ -1,6 +1,7 @@
+use crate::shell::common::state::StateError;
 use std::fmt;
 use std::fmt::{Display, Formatter};
-use std::io::Error;
+use std::io::{Error, ErrorKind};
 
 pub enum BuiltInError {
     NoArgument,
@@ -11,6 +12,22 @@ pub enum BuiltInError {
     InvalidArgument,
     IOError(std::io::Error),
     NoSuchProgram(String),
+    // The path of the program is a directory.
+    IsADirectory(String),
+    // The program at the path can't be executed.
+    PermissionDenied(String),
+    NoSuchJob(String),
+    JobAlreadyInBackground(usize),
+    NotInLoop,
+    NotInFunction,
+    FunctionNestingTooDeep(String),
+    NoSuchOption(String),
+    InvalidIdentifier(String),
+    StateError(StateError),
+    SystemError(nix::Error),
+    BadFileDescriptor(String),
+    // Writing to the file would overwrite it while `noclobber` is set.
+    WouldClobber(String),
 }
 
 impl Display for BuiltInError {
@@ -28,6 +45,45 @@ impl Display for BuiltInError {
                 write!(f, "failed to spawn child for command {}: {}", cmd, e)
             }
             BuiltInError::NoSuchProgram(program) => write!(f, "no such program {}", program),
+            BuiltInError::IsADirectory(path) => write!(f, "{}: Is a directory", path),
+            BuiltInError::PermissionDenied(path) => write!(f, "{}: Permission denied", path),
+            BuiltInError::NoSuchJob(job) => write!(f, "no such job {}", job),
+            BuiltInError::JobAlreadyInBackground(id) => {
+                write!(f, "job {} already in background", id)
+            }
+            BuiltInError::NotInLoop => {
+                write!(f, "only meaningful in a `for`, `while` or `until` loop")
+            }
+            BuiltInError::NotInFunction => write!(f, "only meaningful in a function"),
+            BuiltInError::FunctionNestingTooDeep(name) => {
+                write!(f, "{}: maximum function nesting level exceeded", name)
+            }
+            BuiltInError::NoSuchOption(name) => write!(f, "no such option {}", name),
+            BuiltInError::InvalidIdentifier(name) => {
+                write!(f, "'{}': not a valid identifier", name)
+            }
+            BuiltInError::StateError(e) => write!(f, "{}", e),
+            BuiltInError::SystemError(e) => write!(f, "system error: {}", e),
+            BuiltInError::BadFileDescriptor(fd) => write!(f, "{}: bad file descriptor", fd),
+            BuiltInError::WouldClobber(file) => {
+                write!(f, "{}: cannot overwrite existing file", file)
+            }
+        }
+    }
+}
+
+impl BuiltInError {
+    /// The exit status a command failing with this error results in.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            BuiltInError::NoSuchProgram(_) => 127,
+            BuiltInError::IsADirectory(_) | BuiltInError::PermissionDenied(_) => 126,
+            BuiltInError::FailedToSpawnChild(_, e) => match e.kind() {
+                ErrorKind::NotFound => 127,
+                ErrorKind::PermissionDenied => 126,
+                _ => 1,
+            },
+            _ => 1,
         }
     }
 }
@@ -37,3 +93,15 @@ impl From<std::io::Error> for BuiltInError {
         BuiltInError::IOError(e)
     }
 }
+
+impl From<nix::Error> for BuiltInError {
+    fn from(e: nix::Error) -> Self {
+        BuiltInError::SystemError(e)
+    }
+}
+
+impl From<StateError> for BuiltInError {
+    fn from(e: StateError) -> Self {
+        BuiltInError::StateError(e)
+    }
+}
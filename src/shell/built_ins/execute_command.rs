@@ -1,59 +1,296 @@
 use crate::shell::built_ins::errors::BuiltInError;
-use crate::shell::common::search_path::is_valid_program_name;
-use crate::shell::common::types::{CmdPart, Redirect};
-use std::fs::File;
+use crate::shell::common::job_control::{give_terminal_to, reset_signals};
+use crate::shell::common::search_path::{is_valid_program_name, PATH};
+use crate::shell::common::state::{Environment, Options};
+use crate::shell::common::types::{CmdPart, HereDoc, Redirect};
+use nix::fcntl::{fcntl, FcntlArg, FdFlag, OFlag};
+use nix::unistd::{close, dup2, getpgrp, pipe2, Pid};
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::ErrorKind;
+use std::io::Write;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::os::unix::process::CommandExt;
 use std::process::{Child, Command, Stdio};
+use std::thread;
+
+/// How the process should be set up with regards to job control.
+pub struct JobOptions {
+    pub background: bool,
+    pub job_control: bool,
+    // The process group of the pipeline, `None` makes the process the leader of a new group.
+    pub pgid: Option<Pid>,
+}
 
 pub fn execute_command(
     part: CmdPart,
     output: Option<Stdio>,
     index: usize,
+    options: &JobOptions,
+    shell_options: &Options,
+    env: &Environment,
 ) -> Result<Child, BuiltInError> {
-    let mut redirect_in: Option<String> = None;
-    let mut redirect_out: Option<String> = None;
-    for redirect in part.redirects.iter() {
-        match redirect {
-            Redirect::In(val) => redirect_in = Some(val.clone()),
-            Redirect::Out(val) => redirect_out = Some(val.clone()),
-        }
-    }
-
-    let cmd_out = if let Some(file) = redirect_out {
-        create_redirect_file(file)?
-    } else {
-        match output {
-            None => Stdio::piped(),
-            Some(out) => out,
-        }
+    let cmd_out = match output {
+        None => Stdio::piped(),
+        Some(out) => out,
     };
 
-    let cmd_in = if let Some(file) = redirect_in {
-        open_redirect_file(file)?
+    let cmd_in = if index == 0 && options.background && !options.job_control {
+        // Without job control background jobs can't be stopped when reading from the terminal,
+        // so they shouldn't compete with the shell for the terminal input.
+        Stdio::null()
     } else if index == 0 {
         Stdio::inherit()
     } else {
         Stdio::piped()
     };
 
-    return run_command(part, cmd_out, cmd_in);
+    let redirects = open_redirects(&part.redirects, shell_options.noclobber)?;
+    let child = run_command(part, cmd_out, cmd_in, redirects.actions, options, env)?;
+    write_here_docs(redirects.here_docs);
+    Ok(child)
+}
+
+/// The file descriptors of the shell replaced by redirects, with copies of them (`None` if they weren't open).
+pub type SavedFds = Vec<(RawFd, Option<File>)>;
+
+/// Applies the redirects to the shell itself, e.g. for `{ ...; } > file`,
+/// the descriptors they replace are saved to be restored by `restore_fds` afterwards.
+pub fn redirect_shell(redirects: &[Redirect], noclobber: bool) -> Result<SavedFds, BuiltInError> {
+    let opened = open_redirects(redirects, noclobber)?;
+    let mut saved: SavedFds = vec![];
+    for (fd, _) in opened.actions.iter() {
+        if saved.iter().any(|(s, _)| s == fd) {
+            continue;
+        }
+        let copy = fcntl(*fd, FcntlArg::F_DUPFD_CLOEXEC(FIRST_REDIRECT_FD))
+            .ok()
+            .map(|copy| unsafe { File::from_raw_fd(copy) });
+        saved.push((*fd, copy));
+    }
+
+    // Output printed before the group must end up where stdout pointed to before.
+    let _ = io::stdout().flush();
+    if let Err(e) = apply_redirects(&opened.actions) {
+        restore_fds(saved);
+        return Err(e.into());
+    }
+    write_here_docs(opened.here_docs);
+    Ok(saved)
+}
+
+/// Puts back the descriptors saved by `redirect_shell`.
+pub fn restore_fds(saved: SavedFds) {
+    let _ = io::stdout().flush();
+    for (fd, copy) in saved.into_iter() {
+        let _ = match copy {
+            Some(copy) => dup2(copy.as_raw_fd(), fd).map(|_| ()),
+            None => close(fd),
+        };
+    }
+}
+
+fn write_here_docs(here_docs: Vec<(File, String)>) {
+    for (mut pipe, body) in here_docs.into_iter() {
+        // Written from another thread so that a large body can't block the shell,
+        // the pipe is closed once it has been written.
+        thread::spawn(move || {
+            let _ = pipe.write_all(body.as_bytes());
+        });
+    }
+}
+
+// The lowest file descriptor the files opened for the redirects are moved to,
+// to keep them from being replaced by the redirects of the lower descriptors.
+const FIRST_REDIRECT_FD: RawFd = 10;
+
+/// What a file descriptor of the child is redirected to.
+enum Target {
+    // Opened by the shell, kept open until the child has been spawned.
+    File(File),
+    // A copy of another descriptor of the child.
+    Fd(RawFd),
+    Closed,
 }
 
-fn create_redirect_file(file: String) -> Result<Stdio, BuiltInError> {
-    let val = File::create(file)?;
-    Ok(Stdio::from(val))
+/// The redirects of a command, opened by the shell and applied in the child before it's executed.
+struct Redirects {
+    actions: Vec<(RawFd, Target)>,
+    // The write end of the pipe of each here-document together with its body.
+    here_docs: Vec<(File, String)>,
 }
 
-fn open_redirect_file(file: String) -> Result<Stdio, BuiltInError> {
-    let val = File::open(file)?;
-    Ok(Stdio::from(val))
+fn open_redirects(redirects: &[Redirect], noclobber: bool) -> Result<Redirects, BuiltInError> {
+    let mut opened = Redirects {
+        actions: vec![],
+        here_docs: vec![],
+    };
+    for redirect in redirects.iter() {
+        let (fd, file) = match redirect {
+            Redirect::In(fd, file) => (*fd, File::open(file)?),
+            Redirect::Out(fd, file) => (*fd, create(file, noclobber)?),
+            Redirect::Clobber(fd, file) => (*fd, File::create(file)?),
+            Redirect::Append(fd, file) => (*fd, append_to(file)?),
+            Redirect::OutAll(file) => (1, create(file, noclobber)?),
+            Redirect::AppendAll(file) => (1, append_to(file)?),
+            Redirect::Duplicate(fd, target) => {
+                let target = match target.as_str() {
+                    "-" => Target::Closed,
+                    t => match t.parse::<RawFd>() {
+                        Ok(source) if source >= 0 => Target::Fd(source),
+                        _ => return Err(BuiltInError::BadFileDescriptor(target.clone())),
+                    },
+                };
+                opened.actions.push((*fd, target));
+                continue;
+            }
+            Redirect::HereDoc(HereDoc { body, .. }) => {
+                let (read, write) = pipe()?;
+                opened.here_docs.push((write, body.clone()));
+                (0, read)
+            }
+            Redirect::HereString(word) => {
+                let (read, write) = pipe()?;
+                opened.here_docs.push((write, format!("{}\n", word)));
+                (0, read)
+            }
+        };
+        opened.actions.push((fd, Target::File(move_high(file)?)));
+        // `&>` and `&>>` also send stderr to the file.
+        if let Redirect::OutAll(_) | Redirect::AppendAll(_) = redirect {
+            opened.actions.push((2, Target::Fd(1)));
+        }
+    }
+    Ok(opened)
 }
 
-fn run_command(part: CmdPart, output: Stdio, input: Stdio) -> Result<Child, BuiltInError> {
-    if is_valid_program_name(&part.cmd) == false {
-        return Err(BuiltInError::NoSuchProgram(part.cmd.clone()));
+/// Creates (or truncates) the file, with `noclobber` existing regular files are left alone.
+fn create(file: &str, noclobber: bool) -> Result<File, BuiltInError> {
+    if !noclobber {
+        return Ok(File::create(file)?);
     }
 
-    return match Command::new(&part.cmd)
+    match fs::metadata(file) {
+        // Writing to e.g. `/dev/null` doesn't destroy anything.
+        Ok(metadata) if !metadata.is_file() => Ok(OpenOptions::new().write(true).open(file)?),
+        Ok(_) => Err(BuiltInError::WouldClobber(file.to_string())),
+        // Fails if the file has been created since it was checked.
+        Err(_) => match OpenOptions::new().write(true).create_new(true).open(file) {
+            Ok(f) => Ok(f),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                Err(BuiltInError::WouldClobber(file.to_string()))
+            }
+            Err(e) => Err(e.into()),
+        },
+    }
+}
+
+fn append_to(file: &str) -> Result<File, BuiltInError> {
+    Ok(OpenOptions::new().append(true).create(true).open(file)?)
+}
+
+/// The read and write end of a pipe, closed in the child when it's executed.
+pub fn pipe() -> Result<(File, File), BuiltInError> {
+    let (read, write) = pipe2(OFlag::O_CLOEXEC)?;
+    Ok(unsafe { (File::from_raw_fd(read), File::from_raw_fd(write)) })
+}
+
+fn move_high(file: File) -> Result<File, BuiltInError> {
+    let fd = fcntl(
+        file.as_raw_fd(),
+        FcntlArg::F_DUPFD_CLOEXEC(FIRST_REDIRECT_FD),
+    )?;
+    Ok(unsafe { File::from_raw_fd(fd) })
+}
+
+/// Applies the redirects in the child, in the order they were written.
+fn apply_redirects(actions: &[(RawFd, Target)]) -> io::Result<()> {
+    for (fd, target) in actions.iter() {
+        let source = match target {
+            Target::File(file) => file.as_raw_fd(),
+            Target::Fd(source) => *source,
+            Target::Closed => {
+                // Closing a descriptor that isn't open isn't an error.
+                let _ = close(*fd);
+                continue;
+            }
+        };
+        let result = if source == *fd {
+            // dup2 leaves the descriptor as is, it only needs to stay open in the program.
+            fcntl(*fd, FcntlArg::F_SETFD(FdFlag::empty())).map(|_| ())
+        } else {
+            dup2(source, *fd).map(|_| ())
+        };
+        if let Err(e) = result {
+            let errno = e.as_errno().map(|e| e as i32).unwrap_or(0);
+            return Err(io::Error::from_raw_os_error(errno));
+        }
+    }
+    Ok(())
+}
+
+/// Checks that the program can be run, a path to it (containing a `/`) has to be an executable file
+/// while a name is looked up in the directories of `PATH`.
+fn check_program(name: &str, path: Option<String>) -> Result<(), BuiltInError> {
+    if !name.contains('/') {
+        return match is_valid_program_name(name, path) {
+            true => Ok(()),
+            false => Err(BuiltInError::NoSuchProgram(name.to_string())),
+        };
+    }
+    match fs::metadata(name) {
+        Ok(metadata) if metadata.is_dir() => Err(BuiltInError::IsADirectory(name.to_string())),
+        Ok(metadata) if metadata.permissions().mode() & 0o111 == 0 => {
+            Err(BuiltInError::PermissionDenied(name.to_string()))
+        }
+        Ok(_) => Ok(()),
+        Err(_) => Err(BuiltInError::NoSuchProgram(name.to_string())),
+    }
+}
+
+fn run_command(
+    part: CmdPart,
+    output: Stdio,
+    input: Stdio,
+    redirects: Vec<(RawFd, Target)>,
+    options: &JobOptions,
+    env: &Environment,
+) -> Result<Child, BuiltInError> {
+    let path = match part.env.iter().rev().find(|(name, _)| name == PATH) {
+        Some((_, path)) => Some(path.clone()),
+        None => env.get(PATH),
+    };
+    check_program(&part.cmd, path)?;
+
+    let mut command = Command::new(&part.cmd);
+    for name in env.removed.iter() {
+        command.env_remove(name);
+    }
+    command.envs(&env.exported);
+    // Only set for this command, e.g. `FOO=bar cmd`.
+    command.envs(part.env.iter().cloned());
+    unsafe {
+        command.pre_exec(move || apply_redirects(&redirects));
+    }
+    if options.job_control {
+        command.process_group(options.pgid.map(|p| p.as_raw()).unwrap_or(0));
+        let foreground = !options.background;
+        unsafe {
+            command.pre_exec(move || {
+                // Also done by the shell after spawning, whichever happens first
+                // makes sure the process doesn't get stopped when reading from the terminal.
+                if foreground {
+                    let _ = give_terminal_to(getpgrp());
+                }
+                reset_signals();
+                Ok(())
+            });
+        }
+    }
+
+    match command
         .args(
             part.args
                 .iter()
@@ -66,5 +303,5 @@ fn run_command(part: CmdPart, output: Stdio, input: Stdio) -> Result<Child, Buil
     {
         Ok(c) => Ok(c),
         Err(e) => Err(BuiltInError::FailedToSpawnChild(part.cmd, e)),
-    };
+    }
 }
@@ -0,0 +1,18 @@
+use crate::shell::built_ins::errors::BuiltInError;
+use crate::shell::common::state::State;
+use crate::shell::common::types::Arg;
+
+/// Returns the code that the shell should exit with,
+/// defaults to the status of the last command.
+pub fn handle_exit(args: Vec<Arg>, state: &State) -> Result<i32, BuiltInError> {
+    match args.len() {
+        0 => Ok(state.exit_status),
+        1 => match &args[0] {
+            Arg::Word(code) => code
+                .parse::<i32>()
+                .map_err(|_| BuiltInError::InvalidArgument),
+            _ => Err(BuiltInError::InvalidArgument),
+        },
+        num => Err(BuiltInError::TooManyArguments(num, 1)),
+    }
+}
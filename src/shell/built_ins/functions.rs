@@ -0,0 +1,50 @@
+use crate::shell::built_ins::errors::BuiltInError;
+use crate::shell::common::state::{State, StateError};
+use crate::shell::common::types::{Arg, Assignment};
+
+/// Sets the variables for the rest of the function being run,
+/// their previous values are restored when it returns.
+pub fn handle_local(args: Vec<Arg>, state: &mut State) -> Result<(), BuiltInError> {
+    if state.local_scopes.is_empty() {
+        return Err(BuiltInError::NotInFunction);
+    }
+
+    for arg in args.into_iter() {
+        let (name, value) = match arg {
+            Arg::Assignment(name, Assignment::Word(value))
+            | Arg::Assignment(name, Assignment::String(value)) => (name, value),
+            Arg::Word(name) => (name, String::new()),
+            Arg::String(_) => return Err(BuiltInError::InvalidArgument),
+        };
+
+        if state.readonly.contains(&name) {
+            return Err(StateError::ReadOnly(name).into());
+        }
+        let previous = state.variables.get(&name).cloned();
+        if let Some(scope) = state.local_scopes.last_mut() {
+            // Only the value from before the first `local` in the function is kept.
+            scope.entry(name.clone()).or_insert(previous);
+        }
+        state.set_var(&name, value)?;
+    }
+    Ok(())
+}
+
+/// Returns the exit status the function should return with,
+/// defaults to the status of the last command.
+pub fn handle_return(args: Vec<Arg>, state: &State) -> Result<i32, BuiltInError> {
+    if state.local_scopes.is_empty() {
+        return Err(BuiltInError::NotInFunction);
+    }
+
+    match args.len() {
+        0 => Ok(state.exit_status),
+        1 => match &args[0] {
+            Arg::Word(code) => code
+                .parse::<i32>()
+                .map_err(|_| BuiltInError::InvalidArgument),
+            _ => Err(BuiltInError::InvalidArgument),
+        },
+        num => Err(BuiltInError::TooManyArguments(num, 1)),
+    }
+}
@@ -0,0 +1,148 @@
+use crate::shell::built_ins::errors::BuiltInError;
+use crate::shell::common::job_control::{give_terminal_to, take_terminal_back};
+use crate::shell::common::jobs::{report_finished_jobs, Job, JobStatus};
+use crate::shell::common::signals::set_foreground_pgid;
+use crate::shell::common::state::State;
+use crate::shell::common::types::Arg;
+use crate::shell::handle_command::STOPPED_STATUS;
+use nix::sys::signal::{killpg, Signal};
+use std::io::Write;
+
+pub fn handle_jobs(
+    args: Vec<Arg>,
+    state: &mut State,
+    out: &mut dyn Write,
+) -> Result<(), BuiltInError> {
+    if !args.is_empty() {
+        return Err(BuiltInError::TooManyArguments(args.len(), 0));
+    }
+
+    for job in state.jobs.iter_mut() {
+        match job.update_status()? {
+            JobStatus::Running | JobStatus::Stopped => writeln!(out, "{}", job)?,
+            JobStatus::Done(_) => {}
+        }
+    }
+    report_finished_jobs(&mut state.jobs);
+    Ok(())
+}
+
+/// Continues the job in the foreground and waits for it, returns its exit status.
+/// A job that is stopped again is put back in the job table.
+pub fn handle_fg(args: Vec<Arg>, state: &mut State) -> Result<i32, BuiltInError> {
+    let index = find_job(&args, &state.jobs)?;
+    let mut job = state.jobs.remove(index);
+    println!("{}", job.command);
+
+    let controls_terminal = state.job_control && job.pgid.is_some();
+    if controls_terminal {
+        if let Some(pgid) = job.pgid {
+            give_terminal_to(pgid)?;
+        }
+    }
+    set_foreground_pgid(job.pgid);
+    let result = continue_job(&mut job).and_then(|_| Ok(job.wait()?));
+    set_foreground_pgid(None);
+    if controls_terminal {
+        take_terminal_back()?;
+    }
+
+    match result? {
+        JobStatus::Stopped => {
+            println!();
+            println!("{}", job);
+            state.jobs.push(job);
+            Ok(STOPPED_STATUS)
+        }
+        JobStatus::Done(code) => Ok(code),
+        JobStatus::Running => Ok(0),
+    }
+}
+
+/// Continues a stopped job in the background.
+pub fn handle_bg(args: Vec<Arg>, state: &mut State) -> Result<(), BuiltInError> {
+    let index = find_job(&args, &state.jobs)?;
+    let job = &mut state.jobs[index];
+    match job.update_status()? {
+        JobStatus::Running => Err(BuiltInError::JobAlreadyInBackground(job.id)),
+        JobStatus::Done(_) => Err(BuiltInError::NoSuchJob(job.id.to_string())),
+        JobStatus::Stopped => {
+            continue_job(job)?;
+            println!("[{}] {} &", job.id, job.command);
+            Ok(())
+        }
+    }
+}
+
+fn continue_job(job: &mut Job) -> Result<(), BuiltInError> {
+    if job.status() == JobStatus::Stopped {
+        if let Some(pgid) = job.pgid {
+            killpg(pgid, Signal::SIGCONT)?;
+        }
+        job.mark_continued();
+    }
+    Ok(())
+}
+
+/// Waits for the given jobs (`%N`) or processes (PIDs) to finish,
+/// or all jobs if none are given. Returns the exit status of the last one waited for.
+pub fn handle_wait(args: Vec<Arg>, state: &mut State) -> Result<i32, BuiltInError> {
+    if args.is_empty() {
+        for job in state.jobs.iter_mut() {
+            job.wait()?;
+        }
+        // Stopped jobs would never finish, they are left in the job table.
+        state.jobs.retain(|job| job.status() == JobStatus::Stopped);
+        return Ok(0);
+    }
+
+    let mut code = 0;
+    for arg in args.iter() {
+        let spec = arg.to_string();
+        let index = if spec.starts_with('%') {
+            find_job(std::slice::from_ref(arg), &state.jobs)?
+        } else {
+            let pid = spec
+                .parse::<i32>()
+                .map_err(|_| BuiltInError::InvalidArgument)?;
+            state
+                .jobs
+                .iter()
+                .position(|j| j.has_pid(pid))
+                .ok_or(BuiltInError::NoSuchJob(spec))?
+        };
+        code = match state.jobs[index].wait()? {
+            JobStatus::Stopped => STOPPED_STATUS,
+            JobStatus::Done(code) => {
+                state.jobs.remove(index);
+                code
+            }
+            JobStatus::Running => 0,
+        };
+    }
+    Ok(code)
+}
+
+/// Finds the index of the job given by a job spec (`%N`, `N` or `%%`),
+/// defaults to the most recently started job.
+fn find_job(args: &[Arg], jobs: &[Job]) -> Result<usize, BuiltInError> {
+    let spec = match args.len() {
+        0 => String::from("%%"),
+        1 => args[0].to_string(),
+        num => return Err(BuiltInError::TooManyArguments(num, 1)),
+    };
+
+    let index = match spec.trim_start_matches('%') {
+        "" | "%" | "+" => jobs.len().checked_sub(1),
+        id => {
+            let id = id
+                .parse::<usize>()
+                .map_err(|_| BuiltInError::NoSuchJob(spec.clone()))?;
+            jobs.iter().position(|j| j.id == id)
+        }
+    };
+    index.ok_or(match args.len() {
+        0 => BuiltInError::NoSuchJob(String::from("current")),
+        _ => BuiltInError::NoSuchJob(spec),
+    })
+}
@@ -0,0 +1,37 @@
+use crate::shell::built_ins::errors::BuiltInError;
+use crate::shell::common::state::State;
+use crate::shell::common::types::Arg;
+
+/// Returns the number of enclosing loops to break out of, defaults to 1.
+pub fn handle_break(args: Vec<Arg>, state: &State) -> Result<usize, BuiltInError> {
+    loop_levels(args, state)
+}
+
+/// Returns the number of enclosing loops to continue with the next iteration of
+/// (the innermost ones are left), defaults to 1.
+pub fn handle_continue(args: Vec<Arg>, state: &State) -> Result<usize, BuiltInError> {
+    loop_levels(args, state)
+}
+
+fn loop_levels(args: Vec<Arg>, state: &State) -> Result<usize, BuiltInError> {
+    if state.loop_depth == 0 {
+        return Err(BuiltInError::NotInLoop);
+    }
+
+    let levels = match args.len() {
+        0 => 1,
+        1 => match &args[0] {
+            Arg::Word(n) => n
+                .parse::<usize>()
+                .map_err(|_| BuiltInError::InvalidArgument)?,
+            _ => return Err(BuiltInError::InvalidArgument),
+        },
+        num => return Err(BuiltInError::TooManyArguments(num, 1)),
+    };
+    if levels == 0 {
+        return Err(BuiltInError::InvalidArgument);
+    }
+
+    // Asking for more loops than there are applies to all of them.
+    Ok(levels.min(state.loop_depth))
+}
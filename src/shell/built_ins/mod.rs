@@ -2,4 +2,11 @@ pub mod alias;
 pub mod cd;
 pub mod errors;
 pub mod execute_command;
+pub mod exit;
+pub mod functions;
+pub mod jobs;
+pub mod loop_control;
+pub mod set;
 pub mod set_variable;
+pub mod shopt;
+pub mod variables;
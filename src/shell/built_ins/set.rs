@@ -0,0 +1,68 @@
+use crate::shell::built_ins::errors::BuiltInError;
+use crate::shell::built_ins::variables::quote;
+use crate::shell::common::state::State;
+use crate::shell::common::types::{Arg, Assignment};
+use std::collections::BTreeSet;
+use std::env;
+use std::io::Write;
+
+/// Without arguments all variables are listed. `-o name` sets and `+o name` unsets the option,
+/// a lone `-o` or `+o` lists the options. `-C` and `+C` are short for `-o noclobber` and `+o noclobber`.
+/// `NAME=value` sets the variable.
+pub fn handle_set(
+    args: Vec<Arg>,
+    state: &mut State,
+    out: &mut dyn Write,
+) -> Result<(), BuiltInError> {
+    if args.is_empty() {
+        return list_variables(state, out);
+    }
+
+    let mut args = args.into_iter().peekable();
+    while let Some(arg) = args.next() {
+        match arg {
+            Arg::Word(flag) if flag == "-o" || flag == "+o" => {
+                let name = match args.peek() {
+                    Some(Arg::Word(name)) => name.clone(),
+                    Some(_) => return Err(BuiltInError::InvalidArgument),
+                    None => {
+                        list_options(state, out)?;
+                        continue;
+                    }
+                };
+                args.next();
+                match state.options.get_mut(&name) {
+                    Some(option) => *option = flag == "-o",
+                    None => return Err(BuiltInError::NoSuchOption(name)),
+                }
+            }
+            Arg::Word(flag) if flag == "-C" || flag == "+C" => {
+                state.options.noclobber = flag == "-C";
+            }
+            Arg::Assignment(name, Assignment::Word(value))
+            | Arg::Assignment(name, Assignment::String(value)) => state.set_var(&name, value)?,
+            _ => return Err(BuiltInError::InvalidArgument),
+        }
+    }
+    Ok(())
+}
+
+fn list_variables(state: &State, out: &mut dyn Write) -> Result<(), BuiltInError> {
+    let names = env::vars_os()
+        .filter_map(|(name, _)| name.into_string().ok())
+        .chain(state.variables.keys().cloned())
+        .collect::<BTreeSet<String>>();
+    for name in names.iter() {
+        if let Some(value) = state.get_var(name) {
+            writeln!(out, "{}={}", name, quote(&value))?;
+        }
+    }
+    Ok(())
+}
+
+fn list_options(state: &State, out: &mut dyn Write) -> Result<(), BuiltInError> {
+    for (name, set) in state.options.list() {
+        writeln!(out, "{}\t{}", name, if set { "on" } else { "off" })?;
+    }
+    Ok(())
+}
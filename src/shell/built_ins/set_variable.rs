@@ -1,5 +1,7 @@
+use crate::shell::built_ins::errors::BuiltInError;
 use crate::shell::common::state::State;
 
-pub fn set_variable(key: String, val: String, state: &mut State) {
-    state.variables.insert(key, val);
+pub fn set_variable(key: String, val: String, state: &mut State) -> Result<(), BuiltInError> {
+    state.set_var(&key, val)?;
+    Ok(())
 }
\ No newline at end of file
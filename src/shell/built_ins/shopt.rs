@@ -0,0 +1,62 @@
+use crate::shell::built_ins::errors::BuiltInError;
+use crate::shell::common::state::State;
+use crate::shell::common::types::Arg;
+use std::io::Write;
+
+/// Sets (`-s`) or unsets (`-u`) the given options, without any names the options set (or unset) are listed.
+/// Without a flag the given options (or all of them) are listed and the status is 1 if any of them are unset.
+pub fn handle_shopt(
+    args: Vec<Arg>,
+    state: &mut State,
+    out: &mut dyn Write,
+) -> Result<i32, BuiltInError> {
+    let mut names = args
+        .iter()
+        .map(|arg| match arg {
+            Arg::Word(w) => Ok(w.clone()),
+            _ => Err(BuiltInError::InvalidArgument),
+        })
+        .collect::<Result<Vec<String>, BuiltInError>>()?;
+
+    let value = match names.first().map(|n| n.as_str()) {
+        Some("-s") => Some(true),
+        Some("-u") => Some(false),
+        _ => None,
+    };
+    if value.is_some() {
+        names.remove(0);
+    }
+
+    for name in names.iter() {
+        if state.options.get_mut(name).is_none() {
+            return Err(BuiltInError::NoSuchOption(name.clone()));
+        }
+    }
+
+    match value {
+        Some(value) if !names.is_empty() => {
+            for name in names.iter() {
+                if let Some(option) = state.options.get_mut(name) {
+                    *option = value;
+                }
+            }
+            Ok(0)
+        }
+        _ => {
+            let mut status = 0;
+            for (name, set) in state.options.list() {
+                let listed = match value {
+                    Some(value) => set == value,
+                    None => names.is_empty() || names.iter().any(|n| n == name),
+                };
+                if listed {
+                    writeln!(out, "{}\t{}", name, if set { "on" } else { "off" })?;
+                    if !set {
+                        status = 1;
+                    }
+                }
+            }
+            Ok(status)
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use crate::shell::built_ins::errors::BuiltInError;
+use crate::shell::common::state::State;
+use crate::shell::common::types::{Arg, Assignment};
+use std::collections::BTreeSet;
+use std::env;
+use std::io::Write;
+
+/// Marks the variables as exported so that they're passed on to child processes,
+/// `NAME=value` also sets the variable. Without any names (or with `-p`) the exported variables are listed.
+pub fn handle_export(
+    args: Vec<Arg>,
+    state: &mut State,
+    out: &mut dyn Write,
+) -> Result<(), BuiltInError> {
+    let args = without_print_flag(args);
+    if args.is_empty() {
+        for name in exported_names(state) {
+            match state.get_var(&name) {
+                Some(value) => writeln!(out, "export {}={}", name, quote(&value))?,
+                None => writeln!(out, "export {}", name)?,
+            }
+        }
+        return Ok(());
+    }
+
+    for arg in args.into_iter() {
+        let name = assign(arg, state)?;
+        state.exported.insert(name);
+    }
+    Ok(())
+}
+
+/// Makes the variables read-only, `NAME=value` also sets the variable.
+/// Without any names (or with `-p`) the read-only variables are listed.
+pub fn handle_readonly(
+    args: Vec<Arg>,
+    state: &mut State,
+    out: &mut dyn Write,
+) -> Result<(), BuiltInError> {
+    let args = without_print_flag(args);
+    if args.is_empty() {
+        for name in state.readonly.iter().collect::<BTreeSet<&String>>() {
+            match state.get_var(name) {
+                Some(value) => writeln!(out, "readonly {}={}", name, quote(&value))?,
+                None => writeln!(out, "readonly {}", name)?,
+            }
+        }
+        return Ok(());
+    }
+
+    for arg in args.into_iter() {
+        let name = assign(arg, state)?;
+        state.readonly.insert(name);
+    }
+    Ok(())
+}
+
+/// Removes the variables (`-v`) or functions (`-f`), without a flag
+/// functions are only removed if there is no variable with the same name.
+pub fn handle_unset(args: Vec<Arg>, state: &mut State) -> Result<(), BuiltInError> {
+    let mut names = vec![];
+    for arg in args.into_iter() {
+        match arg {
+            Arg::Word(name) => names.push(name),
+            other => return Err(BuiltInError::InvalidIdentifier(other.to_string())),
+        }
+    }
+
+    let flag = match names.first().map(|n| n.as_str()) {
+        Some("-f") | Some("-v") => Some(names.remove(0)),
+        _ => None,
+    };
+
+    for name in names.into_iter() {
+        if !is_valid_identifier(&name) {
+            return Err(BuiltInError::InvalidIdentifier(name));
+        }
+
+        let is_variable = state.get_var(&name).is_some() || state.exported.contains(&name);
+        match flag.as_deref() {
+            Some("-f") => {
+                state.functions.remove(&name);
+            }
+            None if !is_variable => {
+                state.functions.remove(&name);
+            }
+            _ => state.unset_var(&name)?,
+        }
+    }
+    Ok(())
+}
+
+/// Lists the environment child processes are run with, one `NAME=value` per line.
+pub fn handle_env(state: &State, out: &mut dyn Write) -> Result<(), BuiltInError> {
+    for name in exported_names(state) {
+        if let Some(value) = state.get_var(&name) {
+            writeln!(out, "{}={}", name, value)?;
+        }
+    }
+    Ok(())
+}
+
+/// The names of the variables passed on to child processes, sorted.
+fn exported_names(state: &State) -> BTreeSet<String> {
+    env::vars_os()
+        .filter_map(|(name, _)| name.into_string().ok())
+        .chain(state.exported.iter().cloned())
+        .chain(state.variables.keys().cloned())
+        .filter(|name| state.is_exported(name))
+        .collect()
+}
+
+/// Sets the variable if the argument is an assignment, returns the name of the variable.
+fn assign(arg: Arg, state: &mut State) -> Result<String, BuiltInError> {
+    let (name, value) = match arg {
+        Arg::Assignment(name, Assignment::Word(value))
+        | Arg::Assignment(name, Assignment::String(value)) => (name, Some(value)),
+        Arg::Word(name) => (name, None),
+        Arg::String(s) => return Err(BuiltInError::InvalidIdentifier(s)),
+    };
+
+    if !is_valid_identifier(&name) {
+        return Err(BuiltInError::InvalidIdentifier(name));
+    }
+    if let Some(value) = value {
+        state.set_var(&name, value)?;
+    }
+    Ok(name)
+}
+
+fn without_print_flag(mut args: Vec<Arg>) -> Vec<Arg> {
+    if let Some(Arg::Word(flag)) = args.first() {
+        if flag == "-p" {
+            args.remove(0);
+        }
+    }
+    args
+}
+
+pub fn is_valid_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Quotes the value so that it can be read back by the shell.
+pub fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
@@ -0,0 +1,272 @@
+use crate::shell::common::state::{State, StateError};
+use std::convert::TryFrom;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+pub enum ArithmeticError {
+    DivisionByZero,
+    Overflow,
+    NegativeExponent,
+    InvalidNumber(String),
+    StateError(StateError),
+}
+
+impl Display for ArithmeticError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ArithmeticError::DivisionByZero => write!(f, "division by zero"),
+            ArithmeticError::Overflow => write!(f, "integer overflow"),
+            ArithmeticError::NegativeExponent => write!(f, "exponent less than 0"),
+            ArithmeticError::InvalidNumber(n) => write!(f, "invalid number '{}'", n),
+            ArithmeticError::StateError(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Expr {
+    Number(String),
+    Variable(String),
+    Unary(UnaryOp, Box<Expr>),
+    Binary(Box<Expr>, BinaryOp, Box<Expr>),
+    Conditional(Box<Expr>, Box<Expr>, Box<Expr>),
+    // `name = e`, or `name op= e` if there is an operator.
+    Assign(String, Option<BinaryOp>, Box<Expr>),
+    // `++name` and `--name`.
+    PreIncrement(String, i64),
+    // `name++` and `name--`.
+    PostIncrement(String, i64),
+    Comma(Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum UnaryOp {
+    Negate,
+    Not,
+    BitNot,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Pow,
+    Shl,
+    Shr,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    Eq,
+    Ne,
+    BitAnd,
+    BitXor,
+    BitOr,
+    And,
+    Or,
+}
+
+/// Evaluates the expression, variables are read from and assigned to the variables of the state.
+/// Unset or empty variables evaluate to 0.
+pub fn evaluate(expr: &Expr, state: &mut State) -> Result<i64, ArithmeticError> {
+    Ok(match expr {
+        Expr::Number(n) => parse_number(n)?,
+        Expr::Variable(name) => read_variable(name, state)?,
+        Expr::Unary(op, e) => {
+            let value = evaluate(e, state)?;
+            match op {
+                UnaryOp::Negate => value.checked_neg().ok_or(ArithmeticError::Overflow)?,
+                UnaryOp::Not => (value == 0) as i64,
+                UnaryOp::BitNot => !value,
+            }
+        }
+        // `&&` and `||` only evaluate their right hand side when needed.
+        Expr::Binary(l, BinaryOp::And, r) => {
+            (evaluate(l, state)? != 0 && evaluate(r, state)? != 0) as i64
+        }
+        Expr::Binary(l, BinaryOp::Or, r) => {
+            (evaluate(l, state)? != 0 || evaluate(r, state)? != 0) as i64
+        }
+        Expr::Binary(l, op, r) => {
+            let l = evaluate(l, state)?;
+            let r = evaluate(r, state)?;
+            apply(*op, l, r)?
+        }
+        Expr::Conditional(condition, t, f) => match evaluate(condition, state)? {
+            0 => evaluate(f, state)?,
+            _ => evaluate(t, state)?,
+        },
+        Expr::Assign(name, op, e) => {
+            let value = evaluate(e, state)?;
+            let value = match op {
+                Some(op) => apply(*op, read_variable(name, state)?, value)?,
+                None => value,
+            };
+            assign(name, value, state)?
+        }
+        Expr::PreIncrement(name, delta) => {
+            let value = read_variable(name, state)?
+                .checked_add(*delta)
+                .ok_or(ArithmeticError::Overflow)?;
+            assign(name, value, state)?
+        }
+        Expr::PostIncrement(name, delta) => {
+            let value = read_variable(name, state)?;
+            let incremented = value.checked_add(*delta).ok_or(ArithmeticError::Overflow)?;
+            assign(name, incremented, state)?;
+            value
+        }
+        Expr::Comma(l, r) => {
+            evaluate(l, state)?;
+            evaluate(r, state)?
+        }
+    })
+}
+
+fn apply(op: BinaryOp, l: i64, r: i64) -> Result<i64, ArithmeticError> {
+    let value = match op {
+        BinaryOp::Add => l.checked_add(r),
+        BinaryOp::Sub => l.checked_sub(r),
+        BinaryOp::Mul => l.checked_mul(r),
+        BinaryOp::Div | BinaryOp::Rem if r == 0 => return Err(ArithmeticError::DivisionByZero),
+        BinaryOp::Div => l.checked_div(r),
+        BinaryOp::Rem => l.checked_rem(r),
+        BinaryOp::Pow if r < 0 => return Err(ArithmeticError::NegativeExponent),
+        BinaryOp::Pow => u32::try_from(r).ok().and_then(|r| l.checked_pow(r)),
+        BinaryOp::Shl => u32::try_from(r).ok().and_then(|r| l.checked_shl(r)),
+        BinaryOp::Shr => u32::try_from(r).ok().and_then(|r| l.checked_shr(r)),
+        BinaryOp::Lt => Some((l < r) as i64),
+        BinaryOp::Gt => Some((l > r) as i64),
+        BinaryOp::Le => Some((l <= r) as i64),
+        BinaryOp::Ge => Some((l >= r) as i64),
+        BinaryOp::Eq => Some((l == r) as i64),
+        BinaryOp::Ne => Some((l != r) as i64),
+        BinaryOp::BitAnd => Some(l & r),
+        BinaryOp::BitXor => Some(l ^ r),
+        BinaryOp::BitOr => Some(l | r),
+        BinaryOp::And => Some((l != 0 && r != 0) as i64),
+        BinaryOp::Or => Some((l != 0 || r != 0) as i64),
+    };
+    value.ok_or(ArithmeticError::Overflow)
+}
+
+fn read_variable(name: &str, state: &State) -> Result<i64, ArithmeticError> {
+    match state.get_var(name) {
+        None => Ok(0),
+        Some(value) if value.trim().is_empty() => Ok(0),
+        Some(value) => parse_number(value.trim()),
+    }
+}
+
+fn assign(name: &str, value: i64, state: &mut State) -> Result<i64, ArithmeticError> {
+    state
+        .set_var(name, value.to_string())
+        .map_err(ArithmeticError::StateError)?;
+    Ok(value)
+}
+
+/// Parses a decimal, hexadecimal (`0x` prefix) or octal (`0` prefix) number.
+fn parse_number(n: &str) -> Result<i64, ArithmeticError> {
+    let (digits, negative) = match n.strip_prefix('-') {
+        Some(digits) => (digits, true),
+        None => (n, false),
+    };
+    let parsed = if let Some(hex) = digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        i64::from_str_radix(hex, 16)
+    } else if digits.len() > 1 && digits.starts_with('0') {
+        i64::from_str_radix(&digits[1..], 8)
+    } else {
+        digits.parse::<i64>()
+    };
+
+    match parsed {
+        Ok(value) if negative => Ok(-value),
+        Ok(value) => Ok(value),
+        Err(_) => Err(ArithmeticError::InvalidNumber(n.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arithmetic::ArithmeticParser;
+
+    fn eval(expression: &str, state: &mut State) -> Result<i64, ArithmeticError> {
+        match ArithmeticParser::new().parse(expression) {
+            Ok(expr) => evaluate(&expr, state),
+            Err(e) => panic!("failed to parse '{}': {}", expression, e),
+        }
+    }
+
+    fn value(expression: &str) -> i64 {
+        let mut state = State::for_tests();
+        eval(expression, &mut state).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    #[test]
+    fn operator_precedence() {
+        assert_eq!(value("1 + 2 * 3"), 7);
+        assert_eq!(value("(1 + 2) * 3"), 9);
+        assert_eq!(value("10 - 4 - 3"), 3);
+        assert_eq!(value("7 % 3 * 2"), 2);
+        assert_eq!(value("2 ** 3 ** 2"), 512);
+        assert_eq!(value("1 << 2 + 1"), 8);
+        assert_eq!(value("1 + 2 == 3"), 1);
+        assert_eq!(value("1 | 2 ^ 3 & 4"), 3);
+        assert_eq!(value("0 || 2 && 3"), 1);
+        assert_eq!(value("5 > 3 ? 10 : 20"), 10);
+        assert_eq!(value("010 + 0x10"), 24);
+    }
+
+    #[test]
+    fn assignments_and_increments() {
+        let mut state = State::for_tests();
+        assert_eq!(eval("x = 5", &mut state).ok(), Some(5));
+        assert_eq!(eval("x += 2", &mut state).ok(), Some(7));
+        assert_eq!(eval("x++", &mut state).ok(), Some(7));
+        assert_eq!(eval("--x", &mut state).ok(), Some(7));
+        assert_eq!(eval("x * 2", &mut state).ok(), Some(14));
+        assert_eq!(state.variables.get("x"), Some(&String::from("7")));
+    }
+
+    #[test]
+    fn overflow_is_an_error() {
+        let mut state = State::for_tests();
+        for expression in [
+            "9223372036854775807 + 1",
+            "-9223372036854775807 - 2",
+            "4611686018427387904 * 2",
+            "2 ** 63",
+            "1 << 64",
+        ] {
+            assert!(
+                matches!(eval(expression, &mut state), Err(ArithmeticError::Overflow)),
+                "{}",
+                expression
+            );
+        }
+    }
+
+    #[test]
+    fn division_by_zero_and_negative_exponent() {
+        let mut state = State::for_tests();
+        assert!(matches!(
+            eval("1 / 0", &mut state),
+            Err(ArithmeticError::DivisionByZero)
+        ));
+        assert!(matches!(
+            eval("1 % 0", &mut state),
+            Err(ArithmeticError::DivisionByZero)
+        ));
+        assert!(matches!(
+            eval("2 ** -1", &mut state),
+            Err(ArithmeticError::NegativeExponent)
+        ));
+    }
+}
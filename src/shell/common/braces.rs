@@ -0,0 +1,242 @@
+use std::iter;
+
+/// Performs brace expansion on each (whitespace separated) word in the text,
+/// e.g. `a{b,c}d` becomes `abd acd` and `{1..3}` becomes `1 2 3`.
+/// Quoted strings and substitutions (`$(...)` and `${...}`) are left as is.
+pub fn expand_braces(text: &str) -> String {
+    let chars = text.chars().collect::<Vec<char>>();
+    let mut expanded = String::new();
+    let mut start = 0;
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i].is_whitespace() {
+            expanded += &expand_word(&collect(&chars[start..i])).join(" ");
+            expanded.push(chars[i]);
+            start = i + 1;
+            i += 1;
+        } else {
+            i = skip(&chars, i);
+        }
+    }
+    expanded += &expand_word(&collect(&chars[start.min(chars.len())..])).join(" ");
+    expanded
+}
+
+/// Expands the first brace expression in the word, the alternatives and the rest of the word
+/// are then expanded recursively. `{` that don't start a valid expression are kept as is.
+fn expand_word(word: &str) -> Vec<String> {
+    let chars = word.chars().collect::<Vec<char>>();
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i] != '{' {
+            i = skip(&chars, i);
+            continue;
+        }
+
+        if let Some((close, alternatives)) = brace_expression(&chars, i) {
+            let preamble = collect(&chars[..i]);
+            let postscripts = expand_word(&collect(&chars[close + 1..]));
+            let mut expanded = vec![];
+            for alternative in alternatives.iter() {
+                for alternative in expand_word(alternative) {
+                    for postscript in postscripts.iter() {
+                        expanded.push(format!("{}{}{}", preamble, alternative, postscript));
+                    }
+                }
+            }
+            return expanded;
+        }
+        i += 1;
+    }
+    vec![word.to_string()]
+}
+
+/// Returns the index of the closing brace and the alternatives of the brace expression
+/// starting at `open`, if it's valid, i.e. contains a comma or is a sequence.
+fn brace_expression(chars: &[char], open: usize) -> Option<(usize, Vec<String>)> {
+    let mut depth = 0;
+    let mut commas = vec![];
+    let mut i = open;
+    while i < chars.len() {
+        match chars[i] {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    break;
+                }
+            }
+            ',' if depth == 1 => commas.push(i),
+            _ => {
+                i = skip(chars, i);
+                continue;
+            }
+        }
+        i += 1;
+    }
+    if i >= chars.len() {
+        return None;
+    }
+
+    let close = i;
+    if commas.is_empty() {
+        return sequence(&collect(&chars[open + 1..close])).map(|s| (close, s));
+    }
+
+    let mut alternatives = vec![];
+    let mut start = open + 1;
+    for end in commas.into_iter().chain(iter::once(close)) {
+        alternatives.push(collect(&chars[start..end]));
+        start = end + 1;
+    }
+    Some((close, alternatives))
+}
+
+/// Expands a sequence expression, `x..y` or `x..y..step` where `x` and `y` are either integers or letters.
+/// Integers are zero padded to the same width if either of them has a leading zero.
+fn sequence(content: &str) -> Option<Vec<String>> {
+    let parts = content.split("..").collect::<Vec<&str>>();
+    let (start, end, step) = match parts[..] {
+        [start, end] => (start, end, 1),
+        [start, end, step] => (start, end, step.parse::<i64>().ok()?.checked_abs()?.max(1)),
+        _ => return None,
+    };
+
+    if let (Ok(from), Ok(to)) = (start.parse::<i64>(), end.parse::<i64>()) {
+        let width = if zero_padded(start) || zero_padded(end) {
+            start.len().max(end.len())
+        } else {
+            0
+        };
+        return Some(
+            range(from, to, step)
+                .iter()
+                .map(|n| format!("{:0width$}", n, width = width))
+                .collect(),
+        );
+    }
+
+    let (from, to) = (letter(start)?, letter(end)?);
+    Some(
+        range(from as i64, to as i64, step)
+            .iter()
+            .map(|&c| (c as u8 as char).to_string())
+            .collect(),
+    )
+}
+
+fn zero_padded(n: &str) -> bool {
+    let digits = n.trim_start_matches('-');
+    digits.len() > 1 && digits.starts_with('0')
+}
+
+fn letter(s: &str) -> Option<char> {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii_alphabetic() => Some(c),
+        _ => None,
+    }
+}
+
+/// The values from `start` to `end` (inclusive) counting up or down by `step`.
+fn range(start: i64, end: i64, step: i64) -> Vec<i64> {
+    let step = if start <= end { step } else { -step };
+    let mut values = vec![];
+    let mut n = Some(start);
+    while let Some(value) = n {
+        if (step > 0 && value > end) || (step < 0 && value < end) {
+            break;
+        }
+        values.push(value);
+        n = value.checked_add(step);
+    }
+    values
+}
+
+/// Returns the index after the escaped character, quoted string or substitution starting at `i`,
+/// or `i + 1` for any other character.
+fn skip(chars: &[char], i: usize) -> usize {
+    let end = match chars[i] {
+        '\\' => i + 2,
+        '\'' => match chars[i + 1..].iter().position(|&c| c == '\'') {
+            Some(len) => i + len + 2,
+            None => chars.len(),
+        },
+        '"' => {
+            let mut j = i + 1;
+            while j < chars.len() && chars[j] != '"' {
+                j += if chars[j] == '\\' { 2 } else { 1 };
+            }
+            j + 1
+        }
+        // Command and process substitutions, `$( )`, `<( )` and `>( )`.
+        '$' | '<' | '>' if chars.get(i + 1) == Some(&'(') => matching(chars, i + 1, '(', ')'),
+        '$' if chars.get(i + 1) == Some(&'{') => matching(chars, i + 1, '{', '}'),
+        _ => i + 1,
+    };
+    end.min(chars.len())
+}
+
+/// Returns the index after the bracket closing the one at `i`.
+fn matching(chars: &[char], i: usize, open: char, close: char) -> usize {
+    let mut depth = 0;
+    let mut j = i;
+    while j < chars.len() {
+        if chars[j] == open {
+            depth += 1;
+        } else if chars[j] == close {
+            depth -= 1;
+            if depth == 0 {
+                return j + 1;
+            }
+        } else {
+            j = skip(chars, j);
+            continue;
+        }
+        j += 1;
+    }
+    chars.len()
+}
+
+fn collect(chars: &[char]) -> String {
+    chars.iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sequences_with_step() {
+        assert_eq!(expand_braces("{1..10..3}"), "1 4 7 10");
+        assert_eq!(expand_braces("{10..1..3}"), "10 7 4 1");
+        assert_eq!(expand_braces("{1..3..-1}"), "1 2 3");
+        assert_eq!(expand_braces("{1..3..0}"), "1 2 3");
+        assert_eq!(expand_braces("{a..g..2}"), "a c e g");
+        assert_eq!(expand_braces("{z..w}"), "z y x w");
+    }
+
+    #[test]
+    fn sequences_with_padding() {
+        assert_eq!(expand_braces("{01..10..4}"), "01 05 09");
+        assert_eq!(expand_braces("{8..010}"), "008 009 010");
+        assert_eq!(expand_braces("{-05..1..2}"), "-05 -03 -01 001");
+    }
+
+    #[test]
+    fn alternatives_and_nesting() {
+        assert_eq!(expand_braces("x{1..3}y"), "x1y x2y x3y");
+        assert_eq!(expand_braces("{a,b{1..2}}c"), "ac b1c b2c");
+        assert_eq!(expand_braces("a{,b}"), "a ab");
+    }
+
+    #[test]
+    fn invalid_and_quoted_braces_are_kept() {
+        assert_eq!(expand_braces("{1..a} {a} {1,2"), "{1..a} {a} {1,2");
+        assert_eq!(
+            expand_braces("\"{1,2}\" '{a..c}' \\{1,2}"),
+            "\"{1,2}\" '{a..c}' \\{1,2}"
+        );
+        assert_eq!(expand_braces("${a} $(echo {1,2})"), "${a} $(echo {1,2})");
+    }
+}
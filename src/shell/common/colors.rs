@@ -81,9 +81,10 @@ pub fn test_colors() {
     println!("Foreground:");
     for i in 1..=255 {
         print!(
-            "{}{}",
+            "{}{:<width$}",
             fg_color_code(i),
-            format!("{:<width$}", i, width = char_size as usize)
+            i,
+            width = char_size as usize
         );
         if i % chars_per_line == 0 && i > 0 {
             println!();
@@ -95,9 +96,10 @@ pub fn test_colors() {
     // Background colors
     for i in 1..=255 {
         print!(
-            "{}{}",
+            "{}{:<width$}",
             bg_color_code(i),
-            format!("{:<width$}", i, width = char_size as usize)
+            i,
+            width = char_size as usize
         );
         if i % chars_per_line == 0 && i > 0 {
             println!();
@@ -132,5 +134,5 @@ pub fn bg_color(color: Color) -> String {
 }
 
 pub fn reset_color() -> String {
-    format!("\x1b[0m")
+    "\x1b[0m".to_string()
 }
@@ -0,0 +1,286 @@
+use std::fs;
+use std::path::Path;
+
+/// Whether the text matches the glob pattern. `*` matches any sequence of characters,
+/// `?` any single character and `[...]` any of the characters (or ranges, e.g. `a-z`) in the brackets,
+/// `[!...]` or `[^...]` any character not in them. A `\` makes the next character match literally.
+pub fn glob_matches(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<char>>();
+    let text = text.chars().collect::<Vec<char>>();
+    matches_from(&pattern, &text)
+}
+
+/// Whether the pattern contains any characters with a special meaning in a glob.
+pub fn is_glob(pattern: &str) -> bool {
+    pattern.contains(['*', '?', '['])
+}
+
+/// Expands the pattern to the sorted paths matching it, each `/` separated component is matched separately.
+/// `**` as a whole component matches any number of directories (including none).
+/// Hidden files are only matched by components starting with a `.`.
+pub fn expand_glob(pattern: &str) -> Vec<String> {
+    let (mut prefixes, rest) = match pattern.strip_prefix('/') {
+        Some(rest) => (vec![String::from("/")], rest),
+        None => (vec![String::new()], pattern),
+    };
+
+    let components = rest.split('/').collect::<Vec<&str>>();
+    for (index, component) in components.iter().enumerate() {
+        let last = index + 1 == components.len();
+        let mut next = vec![];
+        for prefix in prefixes.iter() {
+            for path in match_component(prefix, component, last) {
+                if last {
+                    next.push(path);
+                } else if path == *prefix {
+                    // `**` matching no directories.
+                    next.push(path);
+                } else if Path::new(&path).is_dir() {
+                    next.push(format!("{}/", path));
+                }
+            }
+        }
+        prefixes = next;
+    }
+
+    // Literal components are matched without checking that they exist.
+    prefixes.retain(|p| Path::new(p).symlink_metadata().is_ok());
+    prefixes.sort();
+    prefixes.dedup();
+    prefixes
+}
+
+/// Returns the paths in the directory given by the prefix (empty or ending with `/`) matching the component.
+fn match_component(prefix: &str, component: &str, last: bool) -> Vec<String> {
+    let dir = if prefix.is_empty() { "." } else { prefix };
+    if component == "**" {
+        let mut paths = vec![];
+        if !last {
+            paths.push(String::from(prefix));
+        }
+        walk_dir(Path::new(dir), prefix, last, &mut paths);
+        return paths;
+    }
+
+    if !is_glob(component) {
+        return vec![format!("{}{}", prefix, unescape(component))];
+    }
+
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return vec![],
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| !name.starts_with('.') || component.starts_with('.'))
+        .filter(|name| glob_matches(component, name))
+        .map(|name| format!("{}{}", prefix, name))
+        .collect()
+}
+
+/// Adds all the (non hidden) directories below the directory to the paths,
+/// as well as the files if `files` is set. Symbolic links aren't followed.
+fn walk_dir(dir: &Path, prefix: &str, files: bool, paths: &mut Vec<String>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let name = match entry.file_name().into_string() {
+            Ok(name) if !name.starts_with('.') => name,
+            _ => continue,
+        };
+        let path = format!("{}{}", prefix, name);
+        let is_dir = entry.file_type().map(|t| t.is_dir()).unwrap_or(false);
+        if is_dir {
+            paths.push(path.clone());
+            walk_dir(&entry.path(), &format!("{}/", path), files, paths);
+        } else if files {
+            paths.push(path);
+        }
+    }
+}
+
+fn unescape(component: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = component.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unescaped.extend(chars.next()),
+            c => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+/// Matches the pattern one element at a time, a `*` first matches nothing and only the last one is
+/// extended by a character when the rest of the pattern fails, which keeps the matching linear per `*`.
+fn matches_from(pattern: &[char], text: &[char]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    // The pattern after the last `*` and the text it has matched up to.
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if pattern.get(p) == Some(&'*') {
+            p += 1;
+            star = Some((p, t));
+            continue;
+        }
+        match match_one(&pattern[p..], text[t]) {
+            Some(len) => {
+                p += len;
+                t += 1;
+            }
+            None => match star {
+                Some((after, matched)) => {
+                    star = Some((after, matched + 1));
+                    p = after;
+                    t = matched + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Matches the character against the element at the start of the pattern,
+/// returns the length of the element if it matched.
+fn match_one(pattern: &[char], c: char) -> Option<usize> {
+    match pattern.first()? {
+        '?' => Some(1),
+        '[' => match match_bracket(&pattern[1..], c) {
+            Some((true, len)) => Some(1 + len),
+            Some((false, _)) => None,
+            // An unclosed bracket is matched literally.
+            None if c == '[' => Some(1),
+            None => None,
+        },
+        '\\' if pattern.len() > 1 => match pattern[1] == c {
+            true => Some(2),
+            false => None,
+        },
+        &p if p == c => Some(1),
+        _ => None,
+    }
+}
+
+/// Matches the character against the bracket expression at the start of the pattern (after the `[`),
+/// returns whether it matched and the length of the expression, or `None` if the bracket is never closed.
+fn match_bracket(pattern: &[char], c: char) -> Option<(bool, usize)> {
+    let negated = matches!(pattern.first(), Some('!') | Some('^'));
+    let start = if negated { 1 } else { 0 };
+    let mut matched = false;
+    let mut i = start;
+    while i < pattern.len() {
+        let p = pattern[i];
+        // A `]` right after the opening bracket is part of the set.
+        if p == ']' && i > start {
+            return Some((matched != negated, i + 1));
+        }
+
+        if i + 2 < pattern.len() && pattern[i + 1] == '-' && pattern[i + 2] != ']' {
+            matched |= p <= c && c <= pattern[i + 2];
+            i += 3;
+        } else {
+            matched |= p == c;
+            i += 1;
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates the files (and their directories) in a new temporary directory, returns its path.
+    fn create_tree(name: &str, files: &[&str]) -> String {
+        let dir = std::env::temp_dir().join(format!("vrsh-glob-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for file in files {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        dir.to_string_lossy().into_owned()
+    }
+
+    /// Expands the pattern within the directory, returns the matches relative to it.
+    fn expand_in(dir: &str, pattern: &str) -> Vec<String> {
+        expand_glob(&format!("{}/{}", dir, pattern))
+            .into_iter()
+            .map(|path| path[dir.len() + 1..].to_string())
+            .collect()
+    }
+
+    #[test]
+    fn bracket_classes() {
+        assert!(glob_matches("[abc]x", "bx"));
+        assert!(!glob_matches("[abc]x", "dx"));
+        assert!(glob_matches("file[0-9]", "file7"));
+        assert!(!glob_matches("file[0-9]", "filex"));
+        assert!(glob_matches("[!a-c]", "d"));
+        assert!(glob_matches("[^a-c]", "d"));
+        assert!(!glob_matches("[!a-c]", "b"));
+        assert!(glob_matches("[]x]", "]"));
+        assert!(glob_matches("[a-]", "-"));
+        assert!(glob_matches("a[b", "a[b"));
+    }
+
+    #[test]
+    fn stars_questions_and_escapes() {
+        assert!(glob_matches("*.rs", "main.rs"));
+        assert!(!glob_matches("*.rs", "main.rsx"));
+        assert!(glob_matches("a**b", "axyzb"));
+        assert!(glob_matches("?.txt", "a.txt"));
+        assert!(!glob_matches("?.txt", ".txt"));
+        assert!(glob_matches("\\*", "*"));
+        assert!(!glob_matches("\\*", "a"));
+    }
+
+    #[test]
+    fn stars_backtrack_without_blowing_up() {
+        assert!(glob_matches("*a*b", "xaxxab"));
+        assert!(!glob_matches("*a*b", "xaxxa"));
+        assert!(glob_matches("a*\\*", "ab*"));
+        let text = "a".repeat(100);
+        assert!(!glob_matches(&format!("{}b", "a*".repeat(30)), &text));
+        assert!(glob_matches(&"a*".repeat(30), &text));
+    }
+
+    #[test]
+    fn double_star_matches_any_number_of_directories() {
+        let dir = create_tree(
+            "double-star",
+            &["a.rs", "src/b.rs", "src/x/c.rs", "src/x/d.txt"],
+        );
+        assert_eq!(
+            expand_in(&dir, "**/*.rs"),
+            vec!["a.rs", "src/b.rs", "src/x/c.rs"]
+        );
+        assert_eq!(
+            expand_in(&dir, "src/**"),
+            vec!["src/b.rs", "src/x", "src/x/c.rs", "src/x/d.txt"]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn hidden_files_need_a_leading_dot() {
+        let dir = create_tree("hidden", &["shown", ".hidden", ".dir/inner", "sub/.inner"]);
+        assert_eq!(expand_in(&dir, "*"), vec!["shown", "sub"]);
+        assert_eq!(expand_in(&dir, ".*"), vec![".dir", ".hidden"]);
+        assert_eq!(expand_in(&dir, "**/*"), vec!["shown", "sub"]);
+        assert_eq!(expand_in(&dir, "sub/.*"), vec!["sub/.inner"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn no_match_is_empty() {
+        let dir = create_tree("no-match", &["a.txt"]);
+        assert!(expand_in(&dir, "*.rs").is_empty());
+        assert!(expand_in(&dir, "missing/*").is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
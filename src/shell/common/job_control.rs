@@ -0,0 +1,65 @@
+use nix::sys::signal::{kill, signal, SigHandler, Signal};
+use nix::unistd::{getpgrp, getpid, isatty, setpgid, tcgetpgrp, tcsetpgrp, Pid};
+use std::os::unix::io::RawFd;
+
+const TERMINAL: RawFd = 0;
+
+// Ignored by the shell so that only the job in the foreground is stopped by them.
+const JOB_CONTROL_SIGNALS: [Signal; 3] = [Signal::SIGTSTP, Signal::SIGTTIN, Signal::SIGTTOU];
+
+/// Puts the shell in its own process group in the foreground of the terminal.
+/// Returns whether job control is enabled, which requires the input to be a terminal.
+pub fn init_job_control() -> bool {
+    if !isatty(TERMINAL).unwrap_or(false) {
+        return false;
+    }
+
+    // Wait until the shell is started in the foreground.
+    loop {
+        match tcgetpgrp(TERMINAL) {
+            Ok(pgrp) if pgrp == getpgrp() => break,
+            Ok(_) => {
+                let _ = kill(Pid::from_raw(-getpgrp().as_raw()), Signal::SIGTTIN);
+            }
+            Err(_) => return false,
+        }
+    }
+
+    for sig in JOB_CONTROL_SIGNALS.iter() {
+        if let Err(e) = unsafe { signal(*sig, SigHandler::SigIgn) } {
+            println!("vrsh: failed to ignore {} 🚦 {}", sig, e);
+        }
+    }
+
+    // Fails if the shell is already a session leader, in which case it is its own group already.
+    let _ = setpgid(getpid(), getpid());
+    match tcsetpgrp(TERMINAL, getpgrp()) {
+        Ok(_) => true,
+        Err(e) => {
+            println!(
+                "vrsh: failed to take control of the terminal, job control disabled: {}",
+                e
+            );
+            false
+        }
+    }
+}
+
+/// Restores the default handling of the job control signals,
+/// ignored signals are otherwise inherited by the programs the shell executes.
+/// Only uses async-signal-safe functions as it is run in the child between fork and exec.
+pub fn reset_signals() {
+    for sig in JOB_CONTROL_SIGNALS.iter() {
+        let _ = unsafe { signal(*sig, SigHandler::SigDfl) };
+    }
+}
+
+/// Makes the process group the foreground process group of the terminal.
+pub fn give_terminal_to(pgid: Pid) -> nix::Result<()> {
+    tcsetpgrp(TERMINAL, pgid)
+}
+
+/// Makes the shell the foreground process group of the terminal again.
+pub fn take_terminal_back() -> nix::Result<()> {
+    tcsetpgrp(TERMINAL, getpgrp())
+}
@@ -0,0 +1,215 @@
+use crate::shell::common::signals::{child_status_changed, set_interrupted};
+use nix::errno::Errno;
+use nix::sys::signal::Signal;
+use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
+use nix::unistd::Pid;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JobStatus {
+    Running,
+    Stopped,
+    Done(i32),
+}
+
+impl Display for JobStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            JobStatus::Running => write!(f, "Running"),
+            JobStatus::Stopped => write!(f, "Stopped"),
+            JobStatus::Done(0) => write!(f, "Done"),
+            JobStatus::Done(code) => write!(f, "Exit {}", code),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Process {
+    pub pid: Pid,
+    pub status: JobStatus,
+}
+
+impl Process {
+    fn update(&mut self, wait_status: WaitStatus) {
+        self.status = match wait_status {
+            WaitStatus::Exited(_, code) => JobStatus::Done(code),
+            WaitStatus::Signaled(_, sig, _) => JobStatus::Done(128 + sig as i32),
+            WaitStatus::Stopped(_, _) => JobStatus::Stopped,
+            WaitStatus::Continued(_) => JobStatus::Running,
+            _ => self.status,
+        }
+    }
+}
+
+/// The processes of a pipeline, all in the same process group.
+#[derive(Debug)]
+pub struct Job {
+    pub id: usize,
+    pub command: String,
+    pub pgid: Option<Pid>,
+    // In the same order as in the pipeline.
+    pub processes: Vec<Process>,
+}
+
+impl Display for Job {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[{}] {:<10} {}",
+            self.id,
+            self.status().to_string(),
+            self.command
+        )
+    }
+}
+
+impl Job {
+    pub fn new(id: usize, command: String, pgid: Option<Pid>, pids: Vec<Pid>) -> Job {
+        Job {
+            id,
+            command,
+            pgid,
+            processes: pids
+                .into_iter()
+                .map(|pid| Process {
+                    pid,
+                    status: JobStatus::Running,
+                })
+                .collect(),
+        }
+    }
+
+    /// The job is stopped if any of its processes are, and done once all of them are.
+    /// A done job has the exit status of the last process in the pipeline.
+    pub fn status(&self) -> JobStatus {
+        let mut status = JobStatus::Done(0);
+        for process in self.processes.iter() {
+            match process.status {
+                JobStatus::Stopped => return JobStatus::Stopped,
+                JobStatus::Running => status = JobStatus::Running,
+                JobStatus::Done(code) => {
+                    if status != JobStatus::Running {
+                        status = JobStatus::Done(code)
+                    }
+                }
+            }
+        }
+        status
+    }
+
+    /// Checks, without blocking, whether the processes of the job have changed status.
+    pub fn update_status(&mut self) -> nix::Result<JobStatus> {
+        let flags = WaitPidFlag::WNOHANG | WaitPidFlag::WUNTRACED | WaitPidFlag::WCONTINUED;
+        for process in self.processes.iter_mut() {
+            if let JobStatus::Done(_) = process.status {
+                continue;
+            }
+            let wait_status = wait_for(process.pid, flags)?;
+            process.update(wait_status);
+        }
+        Ok(self.status())
+    }
+
+    /// Blocks until all the processes of the job have finished or one of them is stopped.
+    pub fn wait(&mut self) -> nix::Result<JobStatus> {
+        for process in self.processes.iter_mut() {
+            while process.status == JobStatus::Running {
+                let wait_status = wait_for(process.pid, WaitPidFlag::WUNTRACED)?;
+                if let WaitStatus::Signaled(_, Signal::SIGINT, _) = wait_status {
+                    // Stops the rest of the command (e.g. a loop) the job is part of as well.
+                    set_interrupted();
+                }
+                process.update(wait_status);
+            }
+            if process.status == JobStatus::Stopped {
+                return Ok(JobStatus::Stopped);
+            }
+        }
+        Ok(self.status())
+    }
+
+    /// Marks the processes as running again, after they have been sent `SIGCONT`.
+    pub fn mark_continued(&mut self) {
+        for process in self.processes.iter_mut() {
+            if process.status == JobStatus::Stopped {
+                process.status = JobStatus::Running;
+            }
+        }
+    }
+
+    pub fn has_pid(&self, pid: i32) -> bool {
+        self.processes.iter().any(|p| p.pid.as_raw() == pid)
+    }
+}
+
+/// Waits for the process, `SIGWINCH` is handled without `SA_RESTART` and may interrupt the wait,
+/// which is then started again.
+fn wait_for(pid: Pid, flags: WaitPidFlag) -> nix::Result<WaitStatus> {
+    loop {
+        match waitpid(pid, Some(flags)) {
+            Err(nix::Error::Sys(Errno::EINTR)) => continue,
+            result => return result,
+        }
+    }
+}
+
+pub fn next_job_id(jobs: &[Job]) -> usize {
+    jobs.iter().map(|j| j.id).max().unwrap_or(0) + 1
+}
+
+/// Prints the jobs that have finished since the last check and removes them from the job table.
+/// The children are only reaped if a `SIGCHLD` has been received since the last check.
+pub fn report_finished_jobs(jobs: &mut Vec<Job>) {
+    if child_status_changed() {
+        for job in jobs.iter_mut() {
+            if let Err(e) = job.update_status() {
+                println!("vrsh: failed to check status of job {}: {}", job.id, e);
+            }
+        }
+    }
+
+    jobs.retain(|job| match job.status() {
+        JobStatus::Done(_) => {
+            println!("{}", job);
+            false
+        }
+        _ => true,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nix::libc;
+    use nix::sys::pthread::pthread_self;
+    use nix::sys::signal::{sigaction, SaFlags, SigAction, SigHandler, SigSet};
+    use std::process::Command;
+    use std::thread;
+    use std::time::Duration;
+
+    extern "C" fn on_resize(_: libc::c_int) {}
+
+    #[test]
+    fn waiting_goes_on_after_a_resize() {
+        // Installed without `SA_RESTART`, like the handler of the shell.
+        let handler = SigAction::new(
+            SigHandler::Handler(on_resize),
+            SaFlags::empty(),
+            SigSet::empty(),
+        );
+        unsafe { sigaction(Signal::SIGWINCH, &handler) }.unwrap();
+
+        // Reaped by the job rather than through the `Child`.
+        let pid = Command::new("sleep").arg("0.5").spawn().unwrap().id();
+        let pid = Pid::from_raw(pid as i32);
+        let mut job = Job::new(1, String::from("sleep 0.5"), None, vec![pid]);
+        let waiting = pthread_self();
+        let resize = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            unsafe { libc::pthread_kill(waiting, libc::SIGWINCH) };
+        });
+        assert_eq!(job.wait().ok(), Some(JobStatus::Done(0)));
+        resize.join().unwrap();
+    }
+}
@@ -1,4 +1,11 @@
+pub mod arithmetic;
+pub mod braces;
 pub mod colors;
+pub mod glob;
+pub mod job_control;
+pub mod jobs;
 pub mod search_path;
+pub mod signals;
 pub mod state;
+pub mod substitutions;
 pub mod types;
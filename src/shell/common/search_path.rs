@@ -1,10 +1,18 @@
 use std::env;
 use std::path::Path;
 
-pub fn is_valid_program_name(name: &str) -> bool {
+pub const PATH: &str = "PATH";
+
+/// Whether the program exists, names without a `/` are looked up in the directories of the given `PATH`.
+pub fn is_valid_program_name(name: &str, path: Option<String>) -> bool {
     let executable = Path::new(name);
-    if let Some(paths) = env::var_os("PATH") {
-        for dir in env::split_paths(&paths).into_iter() {
+    if name.contains('/') {
+        // A path to the program rather than a name to look up in PATH.
+        return executable.is_file();
+    }
+
+    if let Some(paths) = path {
+        for dir in env::split_paths(&paths) {
             let full_path = dir.join(executable);
             if full_path.is_file() {
                 return true;
@@ -12,5 +20,5 @@ pub fn is_valid_program_name(name: &str) -> bool {
         }
     }
 
-    return false;
+    false
 }
@@ -0,0 +1,125 @@
+use crate::shell::common::state::State;
+use nix::sys::signal::{killpg, sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal};
+use nix::unistd::Pid;
+use signal_hook::consts::{SIGCHLD, SIGINT, SIGQUIT, SIGWINCH};
+use signal_hook::low_level::register;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+
+pub enum SignalError {
+    IO(std::io::Error),
+    SystemError(nix::Error),
+}
+
+impl Display for SignalError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            SignalError::IO(e) => write!(f, "io error: {}", e),
+            SignalError::SystemError(e) => write!(f, "system error: {}", e),
+        }
+    }
+}
+
+impl From<std::io::Error> for SignalError {
+    fn from(e: std::io::Error) -> Self {
+        SignalError::IO(e)
+    }
+}
+
+impl From<nix::Error> for SignalError {
+    fn from(e: nix::Error) -> Self {
+        SignalError::SystemError(e)
+    }
+}
+
+// The process group of the pipeline running in the foreground, 0 when there is none.
+static FOREGROUND_PGID: AtomicI32 = AtomicI32::new(0);
+static CHILD_STATUS_CHANGED: AtomicBool = AtomicBool::new(false);
+// Set when the shell or the pipeline in the foreground is interrupted, stops loops and lists of commands.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+// Starts out set so that the size is read before the first prompt.
+static WINDOW_RESIZED: AtomicBool = AtomicBool::new(true);
+
+const COLUMNS: &str = "COLUMNS";
+const LINES: &str = "LINES";
+
+/// Registers the handlers for the signals the shell cares about.
+/// Must be called after the line editor has been created as it installs its own `SIGWINCH` handler,
+/// which is then called after ours.
+pub fn init_signal_handlers() -> Result<(), SignalError> {
+    // Only async-signal-safe operations are allowed in the handlers.
+    unsafe {
+        // The shell itself is never interrupted, the signals are passed on to the foreground pipeline.
+        // When job control is enabled the terminal sends them directly to the pipeline,
+        // so this only matters for signals sent to the shell explicitly.
+        register(SIGINT, || {
+            INTERRUPTED.store(true, Ordering::SeqCst);
+            forward_to_foreground(Signal::SIGINT)
+        })?;
+        register(SIGQUIT, || forward_to_foreground(Signal::SIGQUIT))?;
+        register(SIGCHLD, || {
+            CHILD_STATUS_CHANGED.store(true, Ordering::SeqCst)
+        })?;
+        register(SIGWINCH, || WINDOW_RESIZED.store(true, Ordering::SeqCst))?;
+        // The line editor relies on its reads being interrupted to redraw the line on resize.
+        let ignore = SigAction::new(SigHandler::SigIgn, SaFlags::empty(), SigSet::empty());
+        let handler = sigaction(Signal::SIGWINCH, &ignore)?;
+        let no_restart = SigAction::new(
+            handler.handler(),
+            handler.flags() - SaFlags::SA_RESTART,
+            handler.mask(),
+        );
+        sigaction(Signal::SIGWINCH, &no_restart)?;
+    }
+    Ok(())
+}
+
+fn forward_to_foreground(signal: Signal) {
+    let pgid = FOREGROUND_PGID.load(Ordering::SeqCst);
+    if pgid > 0 {
+        let _ = killpg(Pid::from_raw(pgid), signal);
+    }
+}
+
+/// Sets the process group that signals to the shell should be forwarded to.
+pub fn set_foreground_pgid(pgid: Option<Pid>) {
+    let pgid = pgid.map(|p| p.as_raw()).unwrap_or(0);
+    FOREGROUND_PGID.store(pgid, Ordering::SeqCst);
+}
+
+/// Marks the command being run as interrupted.
+pub fn set_interrupted() {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+/// Whether the command being run has been interrupted.
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Resets the interrupted flag before running a new command.
+pub fn clear_interrupted() {
+    INTERRUPTED.store(false, Ordering::SeqCst);
+}
+
+/// Whether a child has exited or been stopped since the last call.
+pub fn child_status_changed() -> bool {
+    CHILD_STATUS_CHANGED.swap(false, Ordering::SeqCst)
+}
+
+/// Updates the `COLUMNS` and `LINES` variables if the terminal has been resized.
+pub fn update_window_size(state: &mut State) {
+    if !WINDOW_RESIZED.swap(false, Ordering::SeqCst) {
+        return;
+    }
+
+    if let Ok((columns, lines)) = termion::terminal_size() {
+        state
+            .variables
+            .insert(String::from(COLUMNS), columns.to_string());
+        state
+            .variables
+            .insert(String::from(LINES), lines.to_string());
+    }
+}
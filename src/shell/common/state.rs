@@ -1,4 +1,9 @@
-use std::collections::HashMap;
+use crate::shell::common::jobs::Job;
+use crate::shell::common::substitutions::ProcessSubstitution;
+use crate::shell::common::types::CmdList;
+use nix::unistd::Pid;
+use std::collections::{HashMap, HashSet};
+use std::env;
 use std::env::var_os;
 use std::fmt;
 use std::fmt::{Display, Formatter};
@@ -8,6 +13,7 @@ pub enum StateError {
     EnvVarNotSet(String),
     EnvVarEmpty(String),
     InvalidEnvVar(String),
+    ReadOnly(String),
 }
 
 impl Display for StateError {
@@ -18,6 +24,7 @@ impl Display for StateError {
             StateError::InvalidEnvVar(var) => {
                 write!(f, "environment variable {} set to an invalid value", var)
             }
+            StateError::ReadOnly(var) => write!(f, "{}: readonly variable", var),
         }
     }
 }
@@ -25,28 +32,169 @@ impl Display for StateError {
 const HOME: &str = "HOME";
 const USER: &str = "USER";
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct State {
     pub aliases: HashMap<String, String>,
     pub username: String,
     pub home: String,
     pub variables: HashMap<String, String>,
+    pub exit_status: i32,
+    pub jobs: Vec<Job>,
+    pub last_background_pid: Option<u32>,
+    // Whether the shell controls the terminal and puts each pipeline in its own process group.
+    pub job_control: bool,
+    // The number of loops the command currently being run is nested in.
+    pub loop_depth: usize,
+    pub functions: HashMap<String, CmdList>,
+    // The positional parameters (`$1`, `$2`, ...) of the function being run.
+    pub positional_args: Vec<String>,
+    // The variables shadowed by `local` in each of the functions being run,
+    // they are restored to their previous values (`None` if unset) when the function returns.
+    pub local_scopes: Vec<HashMap<String, Option<String>>>,
+    pub options: Options,
+    // The shell variables passed on to child processes,
+    // the variables in the environment of the shell process always are.
+    pub exported: HashSet<String>,
+    pub readonly: HashSet<String>,
+    // The variables in the environment of the shell process that have been unset.
+    pub unset_env: HashSet<String>,
+    // The process substitutions of the pipelines being run, closed and reaped when their pipeline ends.
+    pub substitutions: Vec<ProcessSubstitution>,
+    // Process substitutions that were still running when their pipeline ended.
+    pub unreaped_substitutions: Vec<Pid>,
+}
+
+impl State {
+    pub fn new(home: String, username: String) -> State {
+        State {
+            aliases: HashMap::new(),
+            username,
+            home,
+            variables: HashMap::new(),
+            exit_status: 0,
+            jobs: vec![],
+            last_background_pid: None,
+            job_control: false,
+            loop_depth: 0,
+            functions: HashMap::new(),
+            positional_args: vec![],
+            local_scopes: vec![],
+            options: Options::default(),
+            exported: HashSet::new(),
+            readonly: HashSet::new(),
+            unset_env: HashSet::new(),
+            substitutions: vec![],
+            unreaped_substitutions: vec![],
+        }
+    }
+
+    /// The value of the variable, variables not set in the shell are looked up in the environment of the process.
+    pub fn get_var(&self, name: &str) -> Option<String> {
+        match self.variables.get(name) {
+            Some(value) => Some(value.clone()),
+            None if self.unset_env.contains(name) => None,
+            None => env::var(name).ok(),
+        }
+    }
+
+    /// Sets the variable, unless it's read-only.
+    pub fn set_var(&mut self, name: &str, value: String) -> Result<(), StateError> {
+        if self.readonly.contains(name) {
+            return Err(StateError::ReadOnly(name.to_string()));
+        }
+        self.unset_env.remove(name);
+        self.variables.insert(name.to_string(), value);
+        Ok(())
+    }
+
+    /// Removes the variable, both from the shell and the environment passed on to child processes.
+    pub fn unset_var(&mut self, name: &str) -> Result<(), StateError> {
+        if self.readonly.contains(name) {
+            return Err(StateError::ReadOnly(name.to_string()));
+        }
+        self.variables.remove(name);
+        self.exported.remove(name);
+        if var_os(name).is_some() {
+            self.unset_env.insert(name.to_string());
+        }
+        Ok(())
+    }
+
+    pub fn is_exported(&self, name: &str) -> bool {
+        self.exported.contains(name) || (var_os(name).is_some() && !self.unset_env.contains(name))
+    }
+
+    /// The changes to the environment of the shell process that child processes should get.
+    pub fn child_environment(&self) -> Environment {
+        Environment {
+            exported: self
+                .variables
+                .iter()
+                .filter(|(name, _)| self.is_exported(name))
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect(),
+            removed: self.unset_env.iter().cloned().collect(),
+        }
+    }
+}
+
+/// The environment of a child process, relative to that of the shell process.
+pub struct Environment {
+    pub exported: HashMap<String, String>,
+    pub removed: Vec<String>,
+}
+
+impl Environment {
+    /// The value the variable has in the environment of the child.
+    pub fn get(&self, name: &str) -> Option<String> {
+        match self.exported.get(name) {
+            Some(value) => Some(value.clone()),
+            None if self.removed.iter().any(|r| r == name) => None,
+            None => env::var(name).ok(),
+        }
+    }
+}
+
+/// The shell options that can be toggled with `shopt` or `set -o`.
+#[derive(Debug, Default)]
+pub struct Options {
+    // Glob patterns that don't match any files are removed instead of being kept as is.
+    pub nullglob: bool,
+    // Glob patterns that don't match any files are an error, takes precedence over `nullglob`.
+    pub failglob: bool,
+    // `>` (and `&>`) refuse to overwrite existing files, `>|` still does.
+    pub noclobber: bool,
+}
+
+impl Options {
+    /// The names and values of all the options.
+    pub fn list(&self) -> Vec<(&'static str, bool)> {
+        vec![
+            ("failglob", self.failglob),
+            ("noclobber", self.noclobber),
+            ("nullglob", self.nullglob),
+        ]
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "failglob" => Some(&mut self.failglob),
+            "noclobber" => Some(&mut self.noclobber),
+            "nullglob" => Some(&mut self.nullglob),
+            _ => None,
+        }
+    }
 }
 
 pub fn new_state() -> Result<State, StateError> {
     let home_dir = get_env_variable(HOME)?;
     let username = get_env_variable(USER)?;
 
-    Ok(State {
-        aliases: HashMap::new(),
-        username,
-        home: home_dir,
-        variables: HashMap::new(),
-    })
+    Ok(State::new(home_dir, username))
 }
 
 fn get_env_variable(var: &str) -> Result<String, StateError> {
-    return match var_os(var) {
+    match var_os(var) {
         Some(os_s) => {
             if os_s.is_empty() {
                 return Err(StateError::EnvVarEmpty(String::from(var)));
@@ -58,5 +206,13 @@ fn get_env_variable(var: &str) -> Result<String, StateError> {
             }
         }
         None => Err(StateError::EnvVarNotSet(String::from(var))),
-    };
+    }
+}
+
+#[cfg(test)]
+impl State {
+    /// A state that doesn't depend on the environment the tests are run in.
+    pub fn for_tests() -> State {
+        State::new(String::from("/home/test"), String::from("test"))
+    }
 }
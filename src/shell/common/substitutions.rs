@@ -0,0 +1,30 @@
+use crate::shell::common::state::State;
+use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
+use nix::unistd::Pid;
+use std::fs::File;
+
+/// A process substitution (`<(cmd)` or `>(cmd)`) of the pipeline being run.
+#[derive(Debug)]
+pub struct ProcessSubstitution {
+    // The forked shell running the command.
+    pub pid: Pid,
+    // The end of the pipe kept by the shell, passed on to the program as `/dev/fd/N`.
+    pub pipe: File,
+}
+
+/// Closes the pipes of the process substitutions added since `first` and reaps their processes,
+/// those that are still running (e.g. reading what was written to `>(cmd)`) are reaped once they finish.
+pub fn finish_substitutions(first: usize, state: &mut State) {
+    if first < state.substitutions.len() {
+        let finished = state.substitutions.split_off(first);
+        state
+            .unreaped_substitutions
+            .extend(finished.into_iter().map(|s| s.pid));
+    }
+    state.unreaped_substitutions.retain(|pid| {
+        matches!(
+            waitpid(*pid, Some(WaitPidFlag::WNOHANG)),
+            Ok(WaitStatus::StillAlive)
+        )
+    });
+}
@@ -1,22 +1,277 @@
 use std::fmt::Formatter;
 use std::fmt;
 
+#[derive(Debug, Clone)]
+pub struct CmdList {
+    pub cmds: Vec<Cmd>,
+}
+
+impl CmdList {
+    /// Marks the last command of the list to be run in the background (`&`).
+    pub fn run_last_in_background(&mut self) {
+        if let Some(cmd) = self.cmds.last_mut() {
+            cmd.background = true;
+        }
+    }
+}
+
+impl fmt::Display for CmdList {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for (index, cmd) in self.cmds.iter().enumerate() {
+            if index > 0 {
+                // A command run in the background already ends with `&`.
+                match self.cmds[index - 1].background {
+                    true => write!(f, " ")?,
+                    false => write!(f, "; ")?,
+                }
+            }
+            write!(f, "{}", cmd)?;
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Cmd {
+    pub first: Pipeline,
+    pub rest: Vec<(ListOperator, Pipeline)>,
+    pub background: bool,
+}
+
+impl fmt::Display for Cmd {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.first)?;
+        for (operator, pipeline) in self.rest.iter() {
+            match operator {
+                ListOperator::And => write!(f, " && {}", pipeline)?,
+                ListOperator::Or => write!(f, " || {}", pipeline)?,
+            }
+        }
+        if self.background {
+            write!(f, " &")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum ListOperator {
+    And,
+    Or,
+}
+
+#[derive(Debug, Clone)]
+pub struct Pipeline {
     pub parts: Vec<CmdType>,
 }
 
+impl fmt::Display for Pipeline {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let parts = self.parts.iter().map(|p| p.to_string()).collect::<Vec<String>>();
+        write!(f, "{}", parts.join(" | "))
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum CmdType {
     Cmd(CmdPart),
-    Variable(String, String)
+    // Assignments without a command, e.g. `A=1 B=2`.
+    Variable(Vec<(String, String)>),
+    If(IfCmd),
+    While(WhileCmd),
+    For(ForCmd),
+    Case(CaseCmd),
+    Function(FunctionDef),
+    Group(GroupCmd),
 }
 
+impl CmdType {
+    /// Sends stderr to the same place as stdout (after any other redirects), used for `|&`.
+    /// Only commands and groups can have redirects, other compound commands are put in a group that has it.
+    pub fn redirect_stderr(&mut self) {
+        let stderr = Redirect::Duplicate(2, String::from("1"));
+        match self {
+            CmdType::Cmd(part) => part.redirects.push(stderr),
+            CmdType::Group(group) => group.redirects.push(stderr),
+            // Nothing is written by these.
+            CmdType::Variable(_) | CmdType::Function(_) => {}
+            _ => {
+                let compound = std::mem::replace(self, CmdType::Variable(vec![]));
+                let cmd = Cmd {
+                    first: Pipeline {
+                        parts: vec![compound],
+                    },
+                    rest: vec![],
+                    background: false,
+                };
+                *self = CmdType::Group(GroupCmd {
+                    body: CmdList { cmds: vec![cmd] },
+                    subshell: false,
+                    redirects: vec![stderr],
+                });
+            }
+        }
+    }
+}
+
+impl fmt::Display for CmdType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            CmdType::Cmd(part) => write!(f, "{}", part),
+            CmdType::Variable(assignments) => {
+                let assignments = assignments
+                    .iter()
+                    .map(|(name, value)| format!("{}={}", name, value))
+                    .collect::<Vec<String>>();
+                write!(f, "{}", assignments.join(" "))
+            }
+            CmdType::If(if_cmd) => write!(f, "{}", if_cmd),
+            CmdType::While(while_cmd) => write!(f, "{}", while_cmd),
+            CmdType::For(for_cmd) => write!(f, "{}", for_cmd),
+            CmdType::Case(case_cmd) => write!(f, "{}", case_cmd),
+            CmdType::Function(def) => write!(f, "{}", def),
+            CmdType::Group(group) => write!(f, "{}", group),
+        }
+    }
+}
+
+// Each condition is paired with the list that is run if it succeeds,
+// they are tried in order (`if` followed by the `elif`s).
+#[derive(Debug, Clone)]
+pub struct IfCmd {
+    pub branches: Vec<(CmdList, CmdList)>,
+    pub otherwise: Option<CmdList>,
+}
+
+impl fmt::Display for IfCmd {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for (index, (condition, body)) in self.branches.iter().enumerate() {
+            let keyword = if index == 0 { "if" } else { "elif" };
+            write!(f, "{} {}; then {}; ", keyword, condition, body)?;
+        }
+        if let Some(otherwise) = &self.otherwise {
+            write!(f, "else {}; ", otherwise)?;
+        }
+        write!(f, "fi")
+    }
+}
+
+// An `until` loop runs as long as the condition fails.
+#[derive(Debug, Clone)]
+pub struct WhileCmd {
+    pub condition: CmdList,
+    pub body: CmdList,
+    pub until: bool,
+}
+
+impl fmt::Display for WhileCmd {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let keyword = if self.until { "until" } else { "while" };
+        write!(f, "{} {}; do {}; done", keyword, self.condition, self.body)
+    }
+}
+
+// The words are expanded when the loop is started.
+#[derive(Debug, Clone)]
+pub struct ForCmd {
+    pub var: String,
+    pub words: Vec<Arg>,
+    pub body: CmdList,
+}
+
+impl fmt::Display for ForCmd {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "for {} in", self.var)?;
+        for word in self.words.iter() {
+            write!(f, " {}", word)?;
+        }
+        write!(f, "; do {}; done", self.body)
+    }
+}
+
+// The word and patterns are expanded when the `case` is run,
+// the body of the first item with a pattern matching the word is run.
+#[derive(Debug, Clone)]
+pub struct CaseCmd {
+    pub word: String,
+    pub items: Vec<CaseItem>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CaseItem {
+    pub patterns: Vec<String>,
+    pub body: CmdList,
+}
+
+impl fmt::Display for CaseCmd {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "case {} in ", self.word)?;
+        for item in self.items.iter() {
+            write!(f, "{}) {};; ", item.patterns.join(" | "), item.body)?;
+        }
+        write!(f, "esac")
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FunctionDef {
+    pub name: String,
+    pub body: CmdList,
+}
+
+impl fmt::Display for FunctionDef {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}() {{ {}; }}", self.name, self.body)
+    }
+}
+
+// A subshell `( ... )` is run in a copy of the shell, changes to its state don't affect the shell,
+// a brace group `{ ...; }` is run in the shell itself.
+#[derive(Debug, Clone)]
+pub struct GroupCmd {
+    pub body: CmdList,
+    pub subshell: bool,
+    pub redirects: Vec<Redirect>,
+}
+
+impl fmt::Display for GroupCmd {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.subshell {
+            true => write!(f, "({})", self.body)?,
+            false => write!(f, "{{ {}; }}", self.body)?,
+        }
+        for redirect in self.redirects.iter() {
+            write!(f, " {}", redirect)?;
+        }
+        Ok(())
+    }
+}
+
+// The command, arguments and redirects are kept unexpanded
+// until the command is about to be executed.
 #[derive(Debug, Clone)]
 pub struct CmdPart {
     pub cmd: String,
     pub args: Vec<Arg>,
     pub redirects: Vec<Redirect>,
+    // The variables set in the environment of the command, e.g. `FOO=bar cmd`.
+    pub env: Vec<(String, String)>,
+}
+
+impl fmt::Display for CmdPart {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for (name, value) in self.env.iter() {
+            write!(f, "{}={} ", name, value)?;
+        }
+        write!(f, "{}", self.cmd)?;
+        for arg in self.args.iter() {
+            write!(f, " {}", arg)?;
+        }
+        for redirect in self.redirects.iter() {
+            write!(f, " {}", redirect)?;
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -52,41 +307,79 @@ pub enum Assignment {
     String(String),
 }
 
+// Redirects are applied in the order they're written, e.g. `> out 2>&1` sends both stdout and stderr to `out`
+// while `2>&1 > out` sends stderr to where stdout went before it was redirected.
 #[derive(Debug, Clone)]
 pub enum Redirect {
-    In(String),
-    Out(String),
+    // `n< file`, n defaults to 0.
+    In(i32, String),
+    // `n> file`, n defaults to 1.
+    Out(i32, String),
+    // `n>| file`, overwrites the file even if `noclobber` is set.
+    Clobber(i32, String),
+    // `n>> file`, n defaults to 1.
+    Append(i32, String),
+    // `n>&m` and `n<&m` make n a copy of m, `n>&-` closes n.
+    Duplicate(i32, String),
+    // `&> file`, both stdout and stderr.
+    OutAll(String),
+    // `&>> file`, both stdout and stderr.
+    AppendAll(String),
+    // `<<EOF`, the body is read from the lines following the command.
+    HereDoc(HereDoc),
+    // `<<< word`, the word followed by a newline is used as the input.
+    HereString(String),
 }
 
-// Initial pass
-
-pub enum InitialCmdOrComment {
-    InitialCmd(InitialCmd),
-    Comment
+impl Redirect {
+    /// The file descriptor the redirect replaces, `&>` and `&>>` replace stderr as well.
+    pub fn fd(&self) -> i32 {
+        match self {
+            Redirect::In(fd, _)
+            | Redirect::Out(fd, _)
+            | Redirect::Clobber(fd, _)
+            | Redirect::Append(fd, _)
+            | Redirect::Duplicate(fd, _) => *fd,
+            Redirect::OutAll(_) | Redirect::AppendAll(_) => 1,
+            Redirect::HereDoc(_) | Redirect::HereString(_) => 0,
+        }
+    }
 }
 
-#[derive(Debug, Clone)]
-pub struct InitialCmd {
-    pub parts: Vec<InitialCmdPart>
+impl fmt::Display for Redirect {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Redirect::In(0, file) => write!(f, "< {}", file),
+            Redirect::In(fd, file) => write!(f, "{}< {}", fd, file),
+            Redirect::Out(1, file) => write!(f, "> {}", file),
+            Redirect::Out(fd, file) => write!(f, "{}> {}", fd, file),
+            Redirect::Clobber(1, file) => write!(f, ">| {}", file),
+            Redirect::Clobber(fd, file) => write!(f, "{}>| {}", fd, file),
+            Redirect::Append(1, file) => write!(f, ">> {}", file),
+            Redirect::Append(fd, file) => write!(f, "{}>> {}", fd, file),
+            Redirect::Duplicate(0, target) => write!(f, "<&{}", target),
+            Redirect::Duplicate(fd, target) => write!(f, "{}>&{}", fd, target),
+            Redirect::OutAll(file) => write!(f, "&> {}", file),
+            Redirect::AppendAll(file) => write!(f, "&>> {}", file),
+            Redirect::HereDoc(doc) => write!(f, "{}", doc),
+            Redirect::HereString(word) => write!(f, "<<< {}", word),
+        }
+    }
 }
 
-#[derive(Debug, Clone)]
-pub enum InitialCmdPart {
-    String(String),
-    SingleQuotedString(String),
-    Calculation(InitialCmd),
+#[derive(Debug, Clone, PartialEq)]
+pub struct HereDoc {
+    // The delimiter as written, quotes included.
+    pub delimiter: String,
+    // `<<-`, leading tabs are removed from the lines of the body.
+    pub strip_tabs: bool,
+    // Whether expansions are performed on the body, quoting the delimiter disables them.
+    pub expand: bool,
+    pub body: String,
 }
 
-// Replacements pass
-
-#[derive(Debug, Clone)]
-pub struct ReplacementsCmd {
-    pub parts: Vec<ReplacementPart>
+impl fmt::Display for HereDoc {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "<<{}{}", if self.strip_tabs { "-" } else { "" }, self.delimiter)
+    }
 }
-
-#[derive(Debug, Clone)]
-pub enum ReplacementPart {
-    String(String),
-    Word(String),
-    Variable(String)
-}
\ No newline at end of file
@@ -0,0 +1,339 @@
+use crate::shell::common::glob::glob_matches;
+use crate::shell::common::state::State;
+use crate::shell::parse_command::{expand_pattern, expand_value, ParseError};
+
+const NOT_SET: &str = "parameter null or not set";
+
+/// Returns the value of the variable, or `None` if it's not set.
+pub fn lookup_var(var: &str, state: &State) -> Option<String> {
+    match var {
+        "?" => Some(state.exit_status.to_string()),
+        "!" => state.last_background_pid.map(|pid| pid.to_string()),
+        "$" => Some(std::process::id().to_string()),
+        "0" => Some(String::from("vrsh")),
+        "#" => Some(state.positional_args.len().to_string()),
+        "@" | "*" => Some(state.positional_args.join(" ")),
+        n if n.chars().all(|c| c.is_ascii_digit()) => match n.parse::<usize>() {
+            Ok(index) if index > 0 => state.positional_args.get(index - 1).cloned(),
+            _ => None,
+        },
+        _ => state.get_var(var),
+    }
+}
+
+/// Returns the value of the variable, unset variables are empty.
+pub fn read_var(var: &str, state: &State) -> String {
+    lookup_var(var, state).unwrap_or_default()
+}
+
+/// Expands the contents of a `${...}` parameter expansion, i.e. `${name}`, `${#name}` or `${name<op><word>}`
+/// where the operator is one of `:-`, `:=`, `:?`, `:+` (or without the `:` to only check if the variable is unset),
+/// `#`, `##`, `%`, `%%`, `/`, `//`, `/#` or `/%`.
+pub fn expand_parameter(parameter: &str, state: &mut State) -> Result<String, ParseError> {
+    if let Some(name) = parameter.strip_prefix('#') {
+        if !name.is_empty() && name_length(name) == name.len() {
+            return Ok(read_var(name, state).chars().count().to_string());
+        }
+    }
+
+    let length = name_length(parameter);
+    if length == 0 {
+        return Err(bad_substitution(parameter));
+    }
+    let (name, operation) = parameter.split_at(length);
+    let value = lookup_var(name, state);
+
+    let (colon, operation) = match operation.strip_prefix(':') {
+        Some(operation) => (true, operation),
+        None => (false, operation),
+    };
+    // With a `:` empty variables are treated the same as unset ones.
+    let set = match &value {
+        Some(value) => !colon || !value.is_empty(),
+        None => false,
+    };
+
+    let mut chars = operation.chars();
+    let op = chars.next();
+    let word = chars.as_str();
+    Ok(match op {
+        None if !colon => value.unwrap_or_default(),
+        Some('-') => match value {
+            Some(value) if set => value,
+            _ => expand_value(word, state)?,
+        },
+        Some('=') => match value {
+            Some(value) if set => value,
+            _ => {
+                if !is_identifier(name) {
+                    return Err(ParseError::ParameterError(
+                        name.to_string(),
+                        String::from("cannot assign in this way"),
+                    ));
+                }
+                let default = expand_value(word, state)?;
+                state.set_var(name, default.clone())?;
+                default
+            }
+        },
+        Some('?') => match value {
+            Some(value) if set => value,
+            _ => {
+                let message = match word {
+                    "" => String::from(NOT_SET),
+                    _ => expand_value(word, state)?,
+                };
+                return Err(ParseError::ParameterError(name.to_string(), message));
+            }
+        },
+        Some('+') if set => expand_value(word, state)?,
+        Some('+') => String::new(),
+        Some('#') if !colon => {
+            let (longest, pattern) = strip_repeated(word, '#');
+            let pattern = expand_pattern(pattern, state)?;
+            let value = value.unwrap_or_default();
+            let end = prefix_end(&value, &pattern, longest).unwrap_or(0);
+            value[end..].to_string()
+        }
+        Some('%') if !colon => {
+            let (longest, pattern) = strip_repeated(word, '%');
+            let pattern = expand_pattern(pattern, state)?;
+            let value = value.unwrap_or_default();
+            let start = suffix_start(&value, &pattern, longest).unwrap_or(value.len());
+            value[..start].to_string()
+        }
+        Some('/') if !colon => {
+            let (all, word) = strip_repeated(word, '/');
+            // `/#` and `/%` only replace a match at the start or end of the value.
+            let (anchor, word) = match word.chars().next() {
+                Some(c) if !all && (c == '#' || c == '%') => (Some(c), &word[1..]),
+                _ => (None, word),
+            };
+            let (pattern, replacement) = match word.find('/') {
+                Some(index) => (&word[..index], &word[index + 1..]),
+                None => (word, ""),
+            };
+            let pattern = expand_pattern(pattern, state)?;
+            let replacement = expand_value(replacement, state)?;
+            let value = value.unwrap_or_default();
+            match anchor {
+                // An empty pattern matches at the anchor, the replacement is prepended or appended.
+                Some('#') => match prefix_end(&value, &pattern, true) {
+                    Some(end) => format!("{}{}", replacement, &value[end..]),
+                    None => value,
+                },
+                Some(_) => match suffix_start(&value, &pattern, true) {
+                    Some(start) => format!("{}{}", &value[..start], replacement),
+                    None => value,
+                },
+                None => replace(&value, &pattern, &replacement, all),
+            }
+        }
+        _ => return Err(bad_substitution(parameter)),
+    })
+}
+
+fn bad_substitution(parameter: &str) -> ParseError {
+    ParseError::BadSubstitution(format!("${{{}}}", parameter))
+}
+
+/// The length of the variable name at the start of the parameter,
+/// either an identifier, a number or a single special character.
+fn name_length(parameter: &str) -> usize {
+    match parameter.chars().next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => parameter
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(parameter.len()),
+        Some(c) if c.is_ascii_digit() => parameter
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(parameter.len()),
+        Some('?') | Some('!') | Some('$') | Some('#') | Some('@') | Some('*') => 1,
+        _ => 0,
+    }
+}
+
+fn is_identifier(name: &str) -> bool {
+    matches!(name.chars().next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+}
+
+/// Strips a second `c` from the start of the word, returns whether it was there.
+fn strip_repeated(word: &str, c: char) -> (bool, &str) {
+    match word.strip_prefix(c) {
+        Some(word) => (true, word),
+        None => (false, word),
+    }
+}
+
+/// The byte index of each character boundary in the value, including its end.
+fn boundaries(value: &str) -> Vec<usize> {
+    value
+        .char_indices()
+        .map(|(i, _)| i)
+        .chain(std::iter::once(value.len()))
+        .collect()
+}
+
+/// The end of the shortest (or longest) prefix of the value matching the pattern.
+fn prefix_end(value: &str, pattern: &str, longest: bool) -> Option<usize> {
+    let mut ends = boundaries(value);
+    if longest {
+        ends.reverse();
+    }
+    ends.into_iter()
+        .find(|&end| glob_matches(pattern, &value[..end]))
+}
+
+/// The start of the shortest (or longest) suffix of the value matching the pattern.
+fn suffix_start(value: &str, pattern: &str, longest: bool) -> Option<usize> {
+    let mut starts = boundaries(value);
+    if !longest {
+        starts.reverse();
+    }
+    starts
+        .into_iter()
+        .find(|&start| glob_matches(pattern, &value[start..]))
+}
+
+/// Replaces the longest match of the pattern (the first one, or all of them) with the replacement.
+fn replace(value: &str, pattern: &str, replacement: &str, all: bool) -> String {
+    if pattern.is_empty() {
+        return value.to_string();
+    }
+
+    let indices = boundaries(value);
+    let mut replaced = String::new();
+    let mut copied = 0;
+    let mut i = 0;
+    while i < indices.len() {
+        let start = indices[i];
+        let end = indices[i..]
+            .iter()
+            .rev()
+            .find(|&&end| glob_matches(pattern, &value[start..end]));
+        match end {
+            Some(&end) if end > start => {
+                replaced += &value[copied..start];
+                replaced += replacement;
+                copied = end;
+                if !all {
+                    break;
+                }
+                i = indices
+                    .iter()
+                    .position(|&index| index == end)
+                    .unwrap_or(i + 1);
+            }
+            _ => i += 1,
+        }
+    }
+    replaced += &value[copied..];
+    replaced
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_state() -> State {
+        let mut state = State::for_tests();
+        state
+            .variables
+            .insert(String::from("p"), String::from("path/to/file.tar.gz"));
+        state.variables.insert(String::from("e"), String::new());
+        state.unset_env.insert(String::from("u"));
+        state
+    }
+
+    fn expand(parameter: &str, state: &mut State) -> String {
+        expand_parameter(parameter, state).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    #[test]
+    fn defaults_and_alternatives() {
+        let mut state = test_state();
+        assert_eq!(expand("e:-d", &mut state), "d");
+        assert_eq!(expand("e-d", &mut state), "");
+        assert_eq!(expand("u-d", &mut state), "d");
+        assert_eq!(expand("p:-d", &mut state), "path/to/file.tar.gz");
+        assert_eq!(expand("e:+a", &mut state), "");
+        assert_eq!(expand("e+a", &mut state), "a");
+        assert_eq!(expand("p:+a", &mut state), "a");
+        assert_eq!(expand("u+a", &mut state), "");
+    }
+
+    #[test]
+    fn assign_default() {
+        let mut state = test_state();
+        assert_eq!(expand("u:=7", &mut state), "7");
+        assert_eq!(state.variables.get("u"), Some(&String::from("7")));
+        assert_eq!(expand("u:=8", &mut state), "7");
+        assert!(matches!(
+            expand_parameter("1:=x", &mut state),
+            Err(ParseError::ParameterError(..))
+        ));
+    }
+
+    #[test]
+    fn error_if_unset() {
+        let mut state = test_state();
+        assert_eq!(expand("p:?", &mut state), "path/to/file.tar.gz");
+        assert_eq!(expand("e?", &mut state), "");
+        match expand_parameter("e:?no value", &mut state) {
+            Err(ParseError::ParameterError(name, message)) => {
+                assert_eq!((name.as_str(), message.as_str()), ("e", "no value"));
+            }
+            _ => panic!("expected an error"),
+        }
+        match expand_parameter("u?", &mut state) {
+            Err(ParseError::ParameterError(_, message)) => assert_eq!(message, NOT_SET),
+            _ => panic!("expected an error"),
+        }
+    }
+
+    #[test]
+    fn length() {
+        let mut state = test_state();
+        assert_eq!(expand("#p", &mut state), "19");
+        assert_eq!(expand("#u", &mut state), "0");
+    }
+
+    #[test]
+    fn prefix_and_suffix_removal() {
+        let mut state = test_state();
+        assert_eq!(expand("p#*/", &mut state), "to/file.tar.gz");
+        assert_eq!(expand("p##*/", &mut state), "file.tar.gz");
+        assert_eq!(expand("p%.*", &mut state), "path/to/file.tar");
+        assert_eq!(expand("p%%.*", &mut state), "path/to/file");
+        assert_eq!(expand("p#x", &mut state), "path/to/file.tar.gz");
+        assert_eq!(expand("p#'*'/", &mut state), "path/to/file.tar.gz");
+    }
+
+    #[test]
+    fn replacements() {
+        let mut state = test_state();
+        assert_eq!(expand("p/t/T", &mut state), "paTh/to/file.tar.gz");
+        assert_eq!(expand("p//t/T", &mut state), "paTh/To/file.Tar.gz");
+        assert_eq!(expand("p/#path/P", &mut state), "P/to/file.tar.gz");
+        assert_eq!(expand("p/#to/X", &mut state), "path/to/file.tar.gz");
+        assert_eq!(expand("p/%gz/GZ", &mut state), "path/to/file.tar.GZ");
+        assert_eq!(expand("p/[ft]/_", &mut state), "pa_h/to/file.tar.gz");
+        assert_eq!(expand("p//.", &mut state), "path/to/filetargz");
+        assert_eq!(expand("p/#/P", &mut state), "Ppath/to/file.tar.gz");
+        assert_eq!(expand("p/%/S", &mut state), "path/to/file.tar.gzS");
+    }
+
+    #[test]
+    fn bad_substitutions() {
+        let mut state = test_state();
+        for parameter in ["", "-x", "p:", "p^x", "p:#x"] {
+            assert!(
+                matches!(
+                    expand_parameter(parameter, &mut state),
+                    Err(ParseError::BadSubstitution(_))
+                ),
+                "{}",
+                parameter
+            );
+        }
+    }
+}
@@ -1,26 +1,70 @@
 use crate::shell::built_ins::alias::handle_alias;
 use crate::shell::built_ins::cd::handle_dir_change;
 use crate::shell::built_ins::errors::BuiltInError;
-use crate::shell::built_ins::execute_command::execute_command;
+use crate::shell::built_ins::execute_command::{
+    execute_command, pipe, redirect_shell, restore_fds, JobOptions,
+};
+use crate::shell::built_ins::exit::handle_exit;
+use crate::shell::built_ins::functions::{handle_local, handle_return};
+use crate::shell::built_ins::jobs::{handle_bg, handle_fg, handle_jobs, handle_wait};
+use crate::shell::built_ins::loop_control::{handle_break, handle_continue};
+use crate::shell::built_ins::set::handle_set;
 use crate::shell::built_ins::set_variable::set_variable;
+use crate::shell::built_ins::shopt::handle_shopt;
+use crate::shell::built_ins::variables::{
+    handle_env, handle_export, handle_readonly, handle_unset,
+};
 use crate::shell::common::colors::{bg_color, fg_color, reset_color, test_colors, Color};
+use crate::shell::common::glob::glob_matches;
+use crate::shell::common::job_control::{give_terminal_to, reset_signals, take_terminal_back};
+use crate::shell::common::jobs::{next_job_id, Job, JobStatus};
+use crate::shell::common::signals::{clear_interrupted, interrupted, set_foreground_pgid};
 use crate::shell::common::state::State;
-use crate::shell::common::types::{Cmd, CmdType};
+use crate::shell::common::substitutions::{finish_substitutions, ProcessSubstitution};
+use crate::shell::common::types::{
+    Arg, CaseCmd, Cmd, CmdList, CmdPart, CmdType, ForCmd, GroupCmd, IfCmd, ListOperator, Pipeline,
+    Redirect, WhileCmd,
+};
+use crate::shell::parse_command::{
+    expand_cmd_part, expand_pattern, expand_redirects, expand_value, expand_words, ParseError,
+};
+use nix::fcntl::OFlag;
+use nix::sys::signal::Signal;
+use nix::unistd::{close, dup2, fork, getpgrp, pipe2, setpgid, ForkResult, Pid};
+use std::collections::HashMap;
 use std::fmt;
 use std::fmt::{Display, Formatter};
-use std::io::Read;
-use std::process::{Child, ChildStdout, Stdio};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd};
+use std::process::{Child, Stdio};
+use std::{io, iter, process};
 use termion::cursor::DetectCursorPos;
 use termion::raw::IntoRawMode;
 
+// The exit status of a job stopped by `SIGTSTP`.
+pub const STOPPED_STATUS: i32 = 128 + Signal::SIGTSTP as i32;
+// The exit status after being interrupted by `SIGINT`.
+pub const INTERRUPTED_STATUS: i32 = 128 + Signal::SIGINT as i32;
+
 pub enum CommandStatus {
     Ok,
-    Exit,
+    Exit(i32),
+    // Leave the given number of enclosing loops.
+    Break(usize),
+    // Leave the given number of enclosing loops minus one and continue with the next iteration of that loop.
+    Continue(usize),
+    // Leave the function being run with the given exit status.
+    Return(i32),
 }
 
+// Guards against running out of stack on infinite recursion.
+const MAX_FUNCTION_DEPTH: usize = 256;
+
 pub enum CommandError {
     IO(std::io::Error),
     BuiltInError(BuiltInError),
+    ExpansionError(Box<ParseError>),
 }
 
 impl Display for CommandError {
@@ -28,6 +72,17 @@ impl Display for CommandError {
         match self {
             CommandError::IO(e) => write!(f, "{}", e),
             CommandError::BuiltInError(e) => write!(f, "{}", e),
+            CommandError::ExpansionError(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl CommandError {
+    /// The exit status a command failing with this error results in.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CommandError::BuiltInError(e) => e.exit_code(),
+            _ => 1,
         }
     }
 }
@@ -38,104 +93,1306 @@ impl From<BuiltInError> for CommandError {
     }
 }
 
+impl From<ParseError> for CommandError {
+    fn from(err: ParseError) -> Self {
+        CommandError::ExpansionError(Box::new(err))
+    }
+}
+
 impl From<std::io::Error> for CommandError {
     fn from(err: std::io::Error) -> Self {
         CommandError::IO(err)
     }
 }
 
-pub fn handle_command(command: Cmd, state: &mut State) -> Result<CommandStatus, CommandError> {
-    match handle_command_with_output(command, Stdio::inherit(), state) {
-        Ok((status, _)) => {
-            // Somewhat ugly hack to make sure we always get a newline after a command.
-            let mut stdout = std::io::stdout().into_raw_mode()?;
-            let (x, _) = stdout.cursor_pos()?;
-            if x != 1 {
-                print!(
-                    "\t{}{}%{}\n",
-                    bg_color(Color::Red),
-                    fg_color(Color::White),
-                    reset_color()
-                )
+/// Executes the commands of the list in order, an error in one
+/// command is reported without stopping the rest of the list.
+pub fn handle_command(command: CmdList, state: &mut State) -> Result<CommandStatus, CommandError> {
+    clear_interrupted();
+    if let (CommandStatus::Exit(code), _) = run_command_list(command, false, state) {
+        return Ok(CommandStatus::Exit(code));
+    }
+
+    // Somewhat ugly hack to make sure we always get a newline after a command.
+    let mut stdout = std::io::stdout().into_raw_mode()?;
+    let (x, _) = stdout.cursor_pos()?;
+    if x != 1 {
+        println!(
+            "\t{}{}%{}",
+            bg_color(Color::Red),
+            fg_color(Color::White),
+            reset_color()
+        )
+    }
+    Ok(CommandStatus::Ok)
+}
+
+/// Runs the commands of the list in order, errors are reported without stopping the rest of the list.
+/// If `capture` is set the output of the commands is collected and returned.
+fn run_command_list(command: CmdList, capture: bool, state: &mut State) -> (CommandStatus, String) {
+    let mut captured = String::new();
+    for cmd in command.cmds.into_iter() {
+        if interrupted() {
+            break;
+        }
+        match handle_command_with_output(cmd, capture, state) {
+            Ok((CommandStatus::Ok, output)) => captured += &output,
+            Ok((status, output)) => {
+                captured += &output;
+                return (status, captured);
             }
-            Ok(status)
+            Err(e) => println!("vrsh: {}", e),
         }
-        Err(e) => Err(e),
     }
+    (CommandStatus::Ok, captured)
 }
 
+/// Runs the pipelines of the command in order, skipping the ones whose
+/// `&&` / `||` condition isn't fulfilled by the status of the previously run pipeline.
+/// The exit status of each pipeline that is run is stored in the state.
+/// If `capture` is set the output of the pipelines is collected and returned.
 fn handle_command_with_output(
     command: Cmd,
-    output: Stdio,
-    state: &mut State,
-) -> Result<(CommandStatus, Option<ChildStdout>), CommandError> {
-    let mut all_prevs: Vec<Child> = Vec::new();
-    let mut output = Some(output);
-    for (index, part) in command.parts.into_iter().enumerate().rev() {
-        match part {
-            CmdType::Cmd(c) => match c.cmd.as_str() {
-                "exit" => return Ok((CommandStatus::Exit, None)),
-                "cd" => match handle_dir_change(c.args) {
-                    Ok(_) => {}
-                    Err(e) => println!("vrsh: {}", e),
-                },
-                "alias" => match handle_alias(c.args, state) {
-                    Ok(_) => {}
-                    Err(e) => println!("vrsh: {}", e),
-                },
-                "vrsh-colors" => {
-                    println!("--------");
-                    test_colors();
-                    println!("--------");
+    capture: bool,
+    state: &mut State,
+) -> Result<(CommandStatus, String), CommandError> {
+    if command.background && !command.rest.is_empty() {
+        return run_background_list(command, state);
+    }
+
+    let mut captured = String::new();
+    let pipelines = iter::once((None, command.first))
+        .chain(command.rest.into_iter().map(|(op, p)| (Some(op), p)));
+    for (operator, pipeline) in pipelines {
+        let should_run = match operator {
+            None => true,
+            Some(ListOperator::And) => state.exit_status == 0,
+            Some(ListOperator::Or) => state.exit_status != 0,
+        };
+        if !should_run {
+            continue;
+        }
+        if interrupted() {
+            break;
+        }
+
+        let substitutions = state.substitutions.len();
+        let result = run_pipeline(pipeline, capture, command.background, state);
+        finish_substitutions(substitutions, state);
+        let status = match result {
+            Ok((status, code, output)) => {
+                state.exit_status = code;
+                captured += &output;
+                status
+            }
+            Err(e) => {
+                println!("vrsh: {}", e);
+                state.exit_status = e.exit_code();
+                CommandStatus::Ok
+            }
+        };
+        if !matches!(status, CommandStatus::Ok) {
+            return Ok((status, captured));
+        }
+    }
+
+    Ok((CommandStatus::Ok, captured))
+}
+
+/// Runs an `&&` / `||` list in the background in a copy of the shell, the list is a single job.
+fn run_background_list(
+    command: Cmd,
+    state: &mut State,
+) -> Result<(CommandStatus, String), CommandError> {
+    let list = Cmd {
+        background: false,
+        ..command
+    };
+    let text = list.to_string();
+    let options = JobOptions {
+        background: true,
+        job_control: state.job_control,
+        pgid: None,
+    };
+    let run = move |state: &mut State| {
+        let (flow, _) = handle_command_with_output(list, false, state)?;
+        Ok((flow, state.exit_status, String::new()))
+    };
+    let process = spawn_part(run, Output::Inherit, 0, &options, state)?;
+    let pgid = match options.job_control {
+        true => Some(process.pid),
+        false => None,
+    };
+    add_background_job(text, pgid, vec![process.pid], state);
+    state.exit_status = 0;
+    Ok((CommandStatus::Ok, String::new()))
+}
+
+/// Adds the processes of a job started in the background to the job table.
+fn add_background_job(text: String, pgid: Option<Pid>, pids: Vec<Pid>, state: &mut State) {
+    let id = next_job_id(&state.jobs);
+    if let Some(last) = pids.last() {
+        println!("[{}] {}", id, last);
+        state.last_background_pid = Some(last.as_raw() as u32);
+    }
+    state.jobs.push(Job::new(id, text, pgid, pids));
+}
+
+/// Runs a single pipeline, returns the exit status of the last command of the pipeline.
+/// A pipeline run in the `background` is added to the job table instead of being waited for,
+/// as is a pipeline in the foreground that gets stopped.
+fn run_pipeline(
+    pipeline: Pipeline,
+    capture: bool,
+    background: bool,
+    state: &mut State,
+) -> Result<(CommandStatus, i32, String), CommandError> {
+    let text = pipeline.to_string();
+    let mut all_prevs: Vec<Pid> = Vec::new();
+    let mut output = if capture {
+        Output::Capture
+    } else {
+        Output::Inherit
+    };
+    let mut captured_from = None;
+    let last_index = pipeline.parts.len() - 1;
+    let mut status = 0;
+    let mut last_is_child = false;
+    let mut options = JobOptions {
+        background,
+        job_control: state.job_control,
+        pgid: None,
+    };
+    // Anything other than a program is run in a copy of the shell when it's part of a pipeline,
+    // built-ins included, so that they don't affect the shell and their output goes through the pipe.
+    let separate = last_index > 0 || background;
+
+    for (index, part) in pipeline.parts.into_iter().enumerate().rev() {
+        let mut spawned = None;
+        // A part that fails is reported without abandoning the parts that have already been spawned.
+        let part_status = match part {
+            CmdType::Cmd(c) => match expand_cmd_part(c, state) {
+                Err(e) => failed_part_status(e.into(), &mut output),
+                Ok(c) if !is_built_in(&c.cmd, &c.args, state) => {
+                    let child = execute_command(
+                        c,
+                        Some(output.take().into()),
+                        index,
+                        &options,
+                        &state.options,
+                        &state.child_environment(),
+                    );
+                    spawned = Some(child.map(Spawned::from).map_err(CommandError::from));
+                    0
                 }
-                _ => {
-                    let mut c = execute_command(c, output, index)?;
-                    output = Some(match c.stdin.take() {
-                        Some(v) => Stdio::from(v),
-                        None => Stdio::inherit(),
-                    });
-
-                    all_prevs.push(c);
+                Ok(c) if !separate => return run_built_in(c, capture, state),
+                Ok(c) => {
+                    let run = move |state: &mut State| run_built_in(c, false, state);
+                    spawned = Some(spawn_part(run, output.take(), index, &options, state));
+                    0
                 }
             },
-            CmdType::Variable(var, val) => set_variable(var, val, state),
+            CmdType::Group(group) if group.subshell || separate => {
+                let run = move |state: &mut State| run_group(group, false, state);
+                spawned = Some(spawn_part(run, output.take(), index, &options, state));
+                0
+            }
+            compound if separate => {
+                let run = move |state: &mut State| {
+                    let pipeline = Pipeline {
+                        parts: vec![compound],
+                    };
+                    run_pipeline(pipeline, false, false, state)
+                };
+                spawned = Some(spawn_part(run, output.take(), index, &options, state));
+                0
+            }
+            CmdType::Variable(assignments) => match assign_variables(assignments, state) {
+                Err(e) => failed_part_status(e, &mut output),
+                Ok(_) => 0,
+            },
+            CmdType::If(if_cmd) => return run_if(if_cmd, capture, state),
+            CmdType::While(while_cmd) => return run_while(while_cmd, capture, state),
+            CmdType::For(for_cmd) => return run_for(for_cmd, capture, state),
+            CmdType::Case(case_cmd) => return run_case(case_cmd, capture, state),
+            CmdType::Group(group) => return run_group(group, capture, state),
+            CmdType::Function(def) => {
+                state.functions.insert(def.name, def.body);
+                0
+            }
+        };
+
+        let part_status = match spawned {
+            None => part_status,
+            Some(Err(e)) => failed_part_status(e, &mut output),
+            Some(Ok(process)) => {
+                if options.job_control && options.pgid.is_none() {
+                    // The first process spawned leads the process group of the pipeline.
+                    if !background {
+                        let _ = give_terminal_to(process.pid);
+                        set_foreground_pgid(Some(process.pid));
+                    }
+                    options.pgid = Some(process.pid);
+                }
+                output = match process.stdin {
+                    Some(stdin) => Output::Pipe(stdin),
+                    None => Output::Inherit,
+                };
+                if process.stdout.is_some() {
+                    captured_from = process.stdout;
+                }
+
+                all_prevs.push(process.pid);
+                last_is_child = last_is_child || index == last_index;
+                0
+            }
+        };
+
+        if index == last_index {
+            status = part_status;
+        }
+    }
+
+    // The input of the first process if the part before it wasn't a process, e.g. a built-in.
+    drop(output);
+    // The last command of the pipeline is spawned first.
+    all_prevs.reverse();
+    if background {
+        add_background_job(text, options.pgid, all_prevs, state);
+        return Ok((CommandStatus::Ok, 0, String::new()));
+    }
+
+    let mut captured = String::new();
+    if let Some(mut out) = captured_from {
+        if let Err(e) = out.read_to_string(&mut captured) {
+            println!("vrsh: failed to read output of child {}", e);
         }
     }
 
-    let mut res = None;
-    for (index, mut child) in all_prevs.into_iter().enumerate() {
-        if index == 0 {
-            res = child.stdout.take()
+    let mut job = Job::new(next_job_id(&state.jobs), text, options.pgid, all_prevs);
+    let wait_result = job.wait();
+    set_foreground_pgid(None);
+    if options.pgid.is_some() {
+        if let Err(e) = take_terminal_back() {
+            println!("vrsh: failed to take back control of the terminal: {}", e);
         }
+    }
 
-        match child.wait() {
-            Ok(_) => {}
-            Err(e) => println!("Failed to wait for child {}", e),
+    match wait_result.map_err(BuiltInError::from)? {
+        JobStatus::Stopped => {
+            println!();
+            println!("{}", job);
+            state.jobs.push(job);
+            status = STOPPED_STATUS;
+        }
+        _ => {
+            if let (true, Some(last)) = (last_is_child, job.processes.last()) {
+                if let JobStatus::Done(code) = last.status {
+                    status = code;
+                }
+            }
         }
     }
 
-    Ok((CommandStatus::Ok, res))
+    Ok((CommandStatus::Ok, status, captured))
 }
 
-pub fn handle_sub_command(command: Cmd, state: &mut State) -> Result<String, CommandError> {
-    match handle_command_with_output(command, Stdio::piped(), state) {
-        Ok((_, child)) => match child {
-            None => Ok("".to_string()),
-            Some(mut c) => {
-                let mut buffer = String::new();
-                match c.read_to_string(&mut buffer) {
-                    Ok(_) => {
-                        if let Some(last) = buffer.chars().last() {
-                            if last == '\n' {
-                                buffer.pop();
-                            }
-                        }
-                        Ok(buffer.replace("\n", " "))
-                    }
-                    Err(_) => Ok("".to_string()),
-                }
+/// Whether the command is run by the shell itself, i.e. it's a function or a built-in.
+fn is_built_in(name: &str, args: &[Arg], state: &State) -> bool {
+    match name {
+        // With arguments the `env` program is run instead.
+        "env" => args.is_empty(),
+        "" | "exit" | "break" | "continue" | "return" | "local" | "cd" | "alias" | "jobs"
+        | "fg" | "bg" | "wait" | "shopt" | "set" | "vrsh-colors" | "export" | "unset"
+        | "readonly" => true,
+        name => state.functions.contains_key(name),
+    }
+}
+
+/// Runs a function or built-in, the exit status is that of the built-in.
+/// The assignments of the command are exported and its redirects applied to the shell for as long as it runs.
+fn run_built_in(
+    mut c: CmdPart,
+    capture: bool,
+    state: &mut State,
+) -> Result<(CommandStatus, i32, String), CommandError> {
+    let redirects = std::mem::take(&mut c.redirects);
+    with_redirects(&redirects, capture, state, |capture, state| {
+        // The command expanded to nothing, e.g. `A=1 $(true)`, the assignments then apply to the shell.
+        if c.cmd.is_empty() {
+            let status = built_in_status(
+                c.env
+                    .into_iter()
+                    .try_for_each(|(name, value)| set_variable(name, value, state))
+                    .map(|_| 0),
+            );
+            return Ok((CommandStatus::Ok, status, String::new()));
+        }
+
+        let mut saved = vec![];
+        let result = match export_temporarily(std::mem::take(&mut c.env), &mut saved, state) {
+            Ok(_) => call_built_in(c, capture, state),
+            Err(e) => Err(e.into()),
+        };
+        restore_variables(saved, state);
+        result
+    })
+}
+
+/// Runs a command in the shell itself with the redirects applied to the shell, they're undone once it finishes.
+/// The command is told whether its output is still captured, it isn't once a redirect sends stdout elsewhere.
+fn with_redirects<F>(
+    redirects: &[Redirect],
+    capture: bool,
+    state: &mut State,
+    run: F,
+) -> Result<(CommandStatus, i32, String), CommandError>
+where
+    F: FnOnce(bool, &mut State) -> Result<(CommandStatus, i32, String), CommandError>,
+{
+    if redirects.is_empty() {
+        return run(capture, state);
+    }
+
+    let saved = redirect_shell(redirects, state.options.noclobber)?;
+    let capture = capture && !redirects.iter().any(|r| r.fd() == 1);
+    // The terminal is found through stdin, so it can't be handed to the commands while stdin is redirected.
+    let job_control = state.job_control;
+    state.job_control = job_control && !redirects.iter().any(|r| r.fd() == 0);
+    let result = run(capture, state);
+    state.job_control = job_control;
+    restore_fds(saved);
+    result
+}
+
+/// A variable as it was before a command's assignment to it.
+struct SavedVariable {
+    name: String,
+    value: Option<String>,
+    exported: bool,
+    // Whether it had been removed from the environment passed on to child processes.
+    unset_env: bool,
+}
+
+/// Sets and exports the variables, what they replace is added to `saved`.
+fn export_temporarily(
+    env: Vec<(String, String)>,
+    saved: &mut Vec<SavedVariable>,
+    state: &mut State,
+) -> Result<(), BuiltInError> {
+    for (name, value) in env.into_iter() {
+        let previous = SavedVariable {
+            name: name.clone(),
+            value: state.variables.get(&name).cloned(),
+            exported: state.exported.contains(&name),
+            unset_env: state.unset_env.contains(&name),
+        };
+        state.set_var(&name, value)?;
+        saved.push(previous);
+        state.exported.insert(name);
+    }
+    Ok(())
+}
+
+fn restore_variables(saved: Vec<SavedVariable>, state: &mut State) {
+    for variable in saved.into_iter().rev() {
+        match variable.value {
+            Some(value) => state.variables.insert(variable.name.clone(), value),
+            None => state.variables.remove(&variable.name),
+        };
+        if !variable.exported {
+            state.exported.remove(&variable.name);
+        }
+        if variable.unset_env {
+            state.unset_env.insert(variable.name);
+        }
+    }
+}
+
+fn call_built_in(
+    c: CmdPart,
+    capture: bool,
+    state: &mut State,
+) -> Result<(CommandStatus, i32, String), CommandError> {
+    if state.functions.contains_key(&c.cmd) {
+        return run_function(c.cmd, c.args, capture, state);
+    }
+
+    // The output of the built-in is collected here when it's captured, e.g. `$(export -p)`.
+    let mut captured = vec![];
+    let mut stdout = io::stdout();
+    let out: &mut dyn Write = match capture {
+        true => &mut captured,
+        false => &mut stdout,
+    };
+    let mut flow = CommandStatus::Ok;
+    let status = match c.cmd.as_str() {
+        "exit" => match handle_exit(c.args, state) {
+            Ok(code) => {
+                flow = CommandStatus::Exit(code);
+                code
             }
+            Err(e) => built_in_status(Err(e)),
         },
-        Err(e) => Err(e),
+        "break" => match handle_break(c.args, state) {
+            Ok(levels) => {
+                flow = CommandStatus::Break(levels);
+                0
+            }
+            Err(e) => built_in_status(Err(e)),
+        },
+        "continue" => match handle_continue(c.args, state) {
+            Ok(levels) => {
+                flow = CommandStatus::Continue(levels);
+                0
+            }
+            Err(e) => built_in_status(Err(e)),
+        },
+        "return" => match handle_return(c.args, state) {
+            Ok(code) => {
+                flow = CommandStatus::Return(code);
+                code
+            }
+            Err(e) => built_in_status(Err(e)),
+        },
+        "local" => built_in_status(handle_local(c.args, state).map(|_| 0)),
+        "cd" => built_in_status(handle_dir_change(c.args).map(|_| 0)),
+        "alias" => built_in_status(handle_alias(c.args, state).map(|_| 0)),
+        "jobs" => built_in_status(handle_jobs(c.args, state, out).map(|_| 0)),
+        "fg" => built_in_status(handle_fg(c.args, state)),
+        "bg" => built_in_status(handle_bg(c.args, state).map(|_| 0)),
+        "wait" => built_in_status(handle_wait(c.args, state)),
+        "shopt" => built_in_status(handle_shopt(c.args, state, out)),
+        "set" => built_in_status(handle_set(c.args, state, out).map(|_| 0)),
+        "vrsh-colors" => {
+            println!("--------");
+            test_colors();
+            println!("--------");
+            0
+        }
+        "export" => built_in_status(handle_export(c.args, state, out).map(|_| 0)),
+        "unset" => built_in_status(handle_unset(c.args, state).map(|_| 0)),
+        "readonly" => built_in_status(handle_readonly(c.args, state, out).map(|_| 0)),
+        "env" => built_in_status(handle_env(state, out).map(|_| 0)),
+        name => {
+            return Err(CommandError::BuiltInError(BuiltInError::NoSuchProgram(
+                name.to_string(),
+            )))
+        }
+    };
+    let output = String::from_utf8_lossy(&captured).into_owned();
+    Ok((flow, status, output))
+}
+
+/// Runs the body of the first branch whose condition succeeds, or the `else` branch if none do.
+/// The exit status is that of the body that was run, or 0 if none were.
+fn run_if(
+    if_cmd: IfCmd,
+    capture: bool,
+    state: &mut State,
+) -> Result<(CommandStatus, i32, String), CommandError> {
+    let mut captured = String::new();
+    let mut body = if_cmd.otherwise;
+    for (condition, branch) in if_cmd.branches.into_iter() {
+        let (status, output) = run_command_list(condition, capture, state);
+        captured += &output;
+        if !matches!(status, CommandStatus::Ok) {
+            return Ok((status, state.exit_status, captured));
+        }
+        if state.exit_status == 0 {
+            body = Some(branch);
+            break;
+        }
+    }
+
+    match body {
+        Some(body) => {
+            let (status, output) = run_command_list(body, capture, state);
+            captured += &output;
+            Ok((status, state.exit_status, captured))
+        }
+        None => Ok((CommandStatus::Ok, 0, captured)),
+    }
+}
+
+/// Runs the body of the group in the shell, with the redirects of the group applied to the shell while it runs.
+/// The exit status is that of the last command run.
+fn run_group(
+    group: GroupCmd,
+    capture: bool,
+    state: &mut State,
+) -> Result<(CommandStatus, i32, String), CommandError> {
+    let redirects = expand_redirects(group.redirects, state)?;
+    let body = group.body;
+    with_redirects(&redirects, capture, state, |capture, state| {
+        let (flow, output) = run_command_list(body, capture, state);
+        Ok((flow, state.exit_status, output))
+    })
+}
+
+/// Runs the body as long as the condition succeeds, or until it succeeds for an `until` loop.
+/// The exit status is that of the last run of the body, or 0 if it was never run.
+fn run_while(
+    while_cmd: WhileCmd,
+    capture: bool,
+    state: &mut State,
+) -> Result<(CommandStatus, i32, String), CommandError> {
+    let mut captured = String::new();
+    let mut status = 0;
+    state.loop_depth += 1;
+    let flow = loop {
+        if interrupted() {
+            break CommandStatus::Ok;
+        }
+
+        let (flow, output) = run_command_list(while_cmd.condition.clone(), capture, state);
+        captured += &output;
+        if let Some(flow) = leave_loop(flow) {
+            break flow;
+        }
+        if (state.exit_status == 0) == while_cmd.until {
+            break CommandStatus::Ok;
+        }
+
+        let (flow, output) = run_command_list(while_cmd.body.clone(), capture, state);
+        captured += &output;
+        status = state.exit_status;
+        if let Some(flow) = leave_loop(flow) {
+            break flow;
+        }
+    };
+    state.loop_depth -= 1;
+    Ok((flow, status, captured))
+}
+
+/// Sets the variables in order, so that the values can refer to the variables assigned before them.
+fn assign_variables(
+    assignments: Vec<(String, String)>,
+    state: &mut State,
+) -> Result<(), CommandError> {
+    for (name, value) in assignments.into_iter() {
+        let value = expand_value(&value, state)?;
+        set_variable(name, value, state)?;
+    }
+    Ok(())
+}
+
+/// Runs the body once for each of the expanded words, with the word assigned to the loop variable.
+/// The exit status is that of the last run of the body, or 0 if it was never run.
+fn run_for(
+    for_cmd: ForCmd,
+    capture: bool,
+    state: &mut State,
+) -> Result<(CommandStatus, i32, String), CommandError> {
+    let words = expand_words(for_cmd.words, state)?;
+    let mut captured = String::new();
+    let mut status = 0;
+    let mut flow = CommandStatus::Ok;
+    state.loop_depth += 1;
+    for word in words.into_iter() {
+        if interrupted() {
+            break;
+        }
+
+        set_variable(for_cmd.var.clone(), word, state)?;
+        let (body_flow, output) = run_command_list(for_cmd.body.clone(), capture, state);
+        captured += &output;
+        status = state.exit_status;
+        if let Some(body_flow) = leave_loop(body_flow) {
+            flow = body_flow;
+            break;
+        }
+    }
+    state.loop_depth -= 1;
+    Ok((flow, status, captured))
+}
+
+/// Runs the body of the first item with a pattern matching the word.
+/// The exit status is that of the body, or 0 if no pattern matched.
+fn run_case(
+    case_cmd: CaseCmd,
+    capture: bool,
+    state: &mut State,
+) -> Result<(CommandStatus, i32, String), CommandError> {
+    let word = expand_value(&case_cmd.word, state)?;
+    for item in case_cmd.items.into_iter() {
+        for pattern in item.patterns.iter() {
+            if glob_matches(&expand_pattern(pattern, state)?, &word) {
+                state.exit_status = 0;
+                let (flow, output) = run_command_list(item.body, capture, state);
+                return Ok((flow, state.exit_status, output));
+            }
+        }
+    }
+    Ok((CommandStatus::Ok, 0, String::new()))
+}
+
+/// Decides whether a loop should be left after its body finished with the given status,
+/// returns the status to leave the loop with if so.
+fn leave_loop(flow: CommandStatus) -> Option<CommandStatus> {
+    match flow {
+        CommandStatus::Ok | CommandStatus::Continue(1) => None,
+        CommandStatus::Break(1) => Some(CommandStatus::Ok),
+        CommandStatus::Break(levels) => Some(CommandStatus::Break(levels - 1)),
+        CommandStatus::Continue(levels) => Some(CommandStatus::Continue(levels - 1)),
+        CommandStatus::Exit(code) => Some(CommandStatus::Exit(code)),
+        CommandStatus::Return(code) => Some(CommandStatus::Return(code)),
+    }
+}
+
+/// Runs the function with the arguments as its positional parameters,
+/// the exit status is the one given to `return` or that of the last command run.
+fn run_function(
+    name: String,
+    args: Vec<Arg>,
+    capture: bool,
+    state: &mut State,
+) -> Result<(CommandStatus, i32, String), CommandError> {
+    if state.local_scopes.len() >= MAX_FUNCTION_DEPTH {
+        return Err(CommandError::BuiltInError(
+            BuiltInError::FunctionNestingTooDeep(name),
+        ));
+    }
+    let body = match state.functions.get(&name) {
+        Some(body) => body.clone(),
+        None => {
+            return Err(CommandError::BuiltInError(BuiltInError::NoSuchProgram(
+                name,
+            )))
+        }
+    };
+
+    let args = args.iter().map(|a| a.to_string()).collect();
+    let caller_args = std::mem::replace(&mut state.positional_args, args);
+    // Loops of the caller can't be left from within the function.
+    let caller_loop_depth = std::mem::replace(&mut state.loop_depth, 0);
+    state.local_scopes.push(HashMap::new());
+
+    let (flow, output) = run_command_list(body, capture, state);
+
+    if let Some(scope) = state.local_scopes.pop() {
+        for (var, value) in scope.into_iter() {
+            match value {
+                Some(value) => state.variables.insert(var, value),
+                None => state.variables.remove(&var),
+            };
+        }
+    }
+    state.loop_depth = caller_loop_depth;
+    state.positional_args = caller_args;
+
+    match flow {
+        CommandStatus::Exit(code) => Ok((CommandStatus::Exit(code), code, output)),
+        CommandStatus::Return(code) => Ok((CommandStatus::Ok, code, output)),
+        _ => Ok((CommandStatus::Ok, state.exit_status, output)),
+    }
+}
+
+/// Reports the error of a part of a pipeline that couldn't be run, returns its exit status.
+fn failed_part_status(e: CommandError, output: &mut Output) -> i32 {
+    println!("vrsh: {}", e);
+    // The earlier parts of the pipeline have nowhere to write their output.
+    *output = Output::Null;
+    e.exit_code()
+}
+
+/// Where the output of a part of a pipeline goes.
+enum Output {
+    // The stdout of the shell.
+    Inherit,
+    // Read by the shell, for the last part of a pipeline whose output is captured.
+    Capture,
+    // The input of the next part of the pipeline.
+    Pipe(File),
+    // Nowhere, the next part of the pipeline couldn't be run.
+    Null,
+}
+
+impl Output {
+    fn take(&mut self) -> Output {
+        std::mem::replace(self, Output::Inherit)
+    }
+}
+
+impl From<Output> for Stdio {
+    fn from(output: Output) -> Self {
+        match output {
+            Output::Inherit => Stdio::inherit(),
+            Output::Capture => Stdio::piped(),
+            Output::Pipe(file) => Stdio::from(file),
+            Output::Null => Stdio::null(),
+        }
+    }
+}
+
+/// A part of a pipeline running in its own process.
+struct Spawned {
+    pid: Pid,
+    // The write end of the pipe to its input, unless it's the first part of the pipeline.
+    stdin: Option<File>,
+    // The read end of the pipe its output is captured from.
+    stdout: Option<File>,
+}
+
+impl From<Child> for Spawned {
+    fn from(mut child: Child) -> Self {
+        Spawned {
+            pid: Pid::from_raw(child.id() as i32),
+            stdin: child.stdin.take().map(|s| File::from(OwnedFd::from(s))),
+            stdout: child.stdout.take().map(|s| File::from(OwnedFd::from(s))),
+        }
+    }
+}
+
+/// Runs a part of a pipeline in a forked copy of the shell, changes it makes to the state
+/// of the shell (variables, the working directory, ...) don't affect the shell itself.
+fn spawn_part<F>(
+    run: F,
+    output: Output,
+    index: usize,
+    options: &JobOptions,
+    state: &mut State,
+) -> Result<Spawned, CommandError>
+where
+    F: FnOnce(&mut State) -> Result<(CommandStatus, i32, String), CommandError>,
+{
+    let input = match index {
+        0 if options.background && !options.job_control => Some((File::open("/dev/null")?, None)),
+        0 => None,
+        _ => {
+            let (read, write) = pipe()?;
+            Some((read, Some(write)))
+        }
+    };
+    let (output, captured_from) = match output {
+        Output::Inherit => (None, None),
+        Output::Capture => {
+            let (read, write) = pipe()?;
+            (Some(write), Some(read))
+        }
+        Output::Pipe(file) => (Some(file), None),
+        Output::Null => (
+            Some(OpenOptions::new().write(true).open("/dev/null")?),
+            None,
+        ),
+    };
+
+    // Anything buffered would otherwise be written by both processes.
+    let _ = io::stdout().flush();
+    match unsafe { fork() } {
+        Ok(ForkResult::Child) => {
+            if options.job_control {
+                let _ = setpgid(
+                    Pid::from_raw(0),
+                    options.pgid.unwrap_or_else(|| Pid::from_raw(0)),
+                );
+                if !options.background {
+                    let _ = give_terminal_to(getpgrp());
+                }
+            }
+            reset_signals();
+            if let Some((read, _)) = &input {
+                let _ = dup2(read.as_raw_fd(), 0);
+            }
+            if let Some(write) = &output {
+                let _ = dup2(write.as_raw_fd(), 1);
+            }
+            drop((input, output, captured_from));
+            state.job_control = false;
+            state.jobs.clear();
+
+            let code = match run(state) {
+                Ok((CommandStatus::Exit(code), _, _)) | Ok((CommandStatus::Return(code), _, _)) => {
+                    code
+                }
+                Ok((_, code, _)) => code,
+                Err(e) => {
+                    println!("vrsh: {}", e);
+                    e.exit_code()
+                }
+            };
+            let _ = io::stdout().flush();
+            process::exit(code);
+        }
+        Ok(ForkResult::Parent { child }) => {
+            if options.job_control {
+                // Also done by the child, whichever happens first.
+                let _ = setpgid(child, options.pgid.unwrap_or(child));
+            }
+            Ok(Spawned {
+                pid: child,
+                stdin: input.and_then(|(_, write)| write),
+                stdout: captured_from,
+            })
+        }
+        Err(e) => Err(BuiltInError::from(e).into()),
+    }
+}
+
+/// Reports the error of a failed built-in on stderr, returns the exit status of the built-in.
+fn built_in_status(result: Result<i32, BuiltInError>) -> i32 {
+    match result {
+        Ok(code) => code,
+        Err(e) => {
+            let _ = writeln!(io::stderr(), "vrsh: {}", e);
+            e.exit_code()
+        }
+    }
+}
+
+pub fn handle_sub_command(command: CmdList, state: &mut State) -> Result<String, CommandError> {
+    let mut buffer = String::new();
+    for cmd in command.cmds.into_iter() {
+        let (_, output) = handle_command_with_output(cmd, true, state)?;
+        buffer += &output;
+    }
+
+    if let Some(last) = buffer.chars().last() {
+        if last == '\n' {
+            buffer.pop();
+        }
+    }
+    Ok(buffer.replace('\n', " "))
+}
+
+/// Runs the command of a process substitution in a forked copy of the shell connected to it through a pipe,
+/// returns the `/dev/fd/N` path of the end kept by the shell. The output of the command can be read from it
+/// for `<(cmd)` (`read`), for `>(cmd)` what is written to it is the input of the command.
+pub fn handle_process_substitution(
+    command: CmdList,
+    read: bool,
+    state: &mut State,
+) -> Result<String, CommandError> {
+    let (read_end, write_end) = pipe2(OFlag::O_CLOEXEC).map_err(BuiltInError::from)?;
+    let (kept, given, target) = match read {
+        true => (read_end, write_end, 1),
+        false => (write_end, read_end, 0),
+    };
+
+    // Anything buffered would otherwise be written by both processes.
+    let _ = io::stdout().flush();
+    match unsafe { fork() } {
+        Ok(ForkResult::Child) => {
+            let _ = dup2(given, target);
+            let _ = close(given);
+            let _ = close(kept);
+            // The pipes of the other substitutions must be closed for them to see the end of their input.
+            state.substitutions.clear();
+            state.job_control = false;
+            reset_signals();
+            for cmd in command.cmds.into_iter() {
+                if let Ok((CommandStatus::Exit(code), _)) =
+                    handle_command_with_output(cmd, false, state)
+                {
+                    process::exit(code);
+                }
+            }
+            process::exit(state.exit_status);
+        }
+        Ok(ForkResult::Parent { child }) => {
+            let _ = close(given);
+            state.substitutions.push(ProcessSubstitution {
+                pid: child,
+                pipe: unsafe { File::from_raw_fd(kept) },
+            });
+            Ok(format!("/dev/fd/{}", kept))
+        }
+        Err(e) => {
+            let _ = close(given);
+            let _ = close(kept);
+            Err(BuiltInError::from(e).into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shell::common::jobs::JobStatus;
+    use crate::shell::parse_command::parse_input;
+    use std::fs;
+
+    /// Runs the line and returns its output, the way a command substitution does.
+    fn output(line: &str, state: &mut State) -> String {
+        let command = parse_input(line.to_string()).unwrap_or_else(|e| panic!("{}", e));
+        handle_sub_command(command, state).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Kills the processes of the jobs left in the job table.
+    fn kill_jobs(state: &mut State) {
+        for job in state.jobs.iter_mut() {
+            for process in job.processes.iter_mut() {
+                let _ = nix::sys::signal::kill(process.pid, nix::sys::signal::Signal::SIGKILL);
+                let _ = nix::sys::wait::waitpid(process.pid, None);
+            }
+        }
+        state.jobs.clear();
+    }
+
+    /// A new empty temporary directory for the files a test writes.
+    fn temp_dir(name: &str) -> String {
+        let dir = std::env::temp_dir().join(format!("vrsh-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn and_or_lists_run_the_right_hand_side_on_the_status() {
+        let mut state = State::for_tests();
+        assert_eq!(output("true && echo a || echo b", &mut state), "a");
+        assert_eq!(output("false && echo a || echo b", &mut state), "b");
+        assert_eq!(output("false || false && echo c", &mut state), "");
+        assert_eq!(output("true || echo d && echo e", &mut state), "e");
+    }
+
+    #[test]
+    fn lists_run_every_command_in_order() {
+        let mut state = State::for_tests();
+        assert_eq!(output("echo a; echo b\necho c", &mut state), "a b c");
+        assert_eq!(
+            output("echo 'a;b' \"c;d\"; echo e", &mut state),
+            "a;b c;d e"
+        );
+        assert_eq!(output("echo a # ; echo b", &mut state), "a");
+        assert_eq!(
+            output("vrsh-no-such-program; echo after", &mut state),
+            "after"
+        );
+    }
+
+    #[test]
+    fn exit_status_is_kept_in_dollar_question() {
+        let mut state = State::for_tests();
+        assert_eq!(output("false; echo $?", &mut state), "1");
+        assert_eq!(output("true; echo $?", &mut state), "0");
+        assert_eq!(output("false || echo $?", &mut state), "1");
+        assert_eq!(output("false && true; echo $?", &mut state), "1");
+        assert_eq!(output("true && false || echo $?", &mut state), "1");
+        assert_eq!(output("false | true; echo $?", &mut state), "0");
+    }
+
+    #[test]
+    fn exit_status_of_failures() {
+        let mut state = State::for_tests();
+        assert_eq!(output("vrsh-no-such-program; echo $?", &mut state), "127");
+        assert_eq!(output("./vrsh-no-such-program; echo $?", &mut state), "127");
+        assert_eq!(output("/tmp; echo $?", &mut state), "126");
+        let dir = temp_dir("not-executable");
+        fs::write(format!("{}/script", dir), "echo a").unwrap();
+        let line = format!("{}/script; echo $?", dir);
+        assert_eq!(output(&line, &mut state), "126");
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(output("sh -c 'kill -TERM $$'; echo $?", &mut state), "143");
+    }
+
+    #[test]
+    fn background_jobs_are_waited_for() {
+        let mut state = State::for_tests();
+        assert_eq!(output("sh -c 'exit 3' &", &mut state), "");
+        assert_eq!(state.jobs.len(), 1);
+        assert_eq!(output("wait %1; echo $?", &mut state), "3");
+        assert!(state.jobs.is_empty());
+        assert_eq!(output("sleep 0 & sleep 0 &", &mut state), "");
+        assert_eq!(state.jobs.len(), 2);
+        assert_eq!(output("wait; echo $?", &mut state), "0");
+        assert!(state.jobs.is_empty());
+    }
+
+    #[test]
+    fn stopped_pipelines_are_added_to_the_job_table() {
+        let mut state = State::for_tests();
+        let command =
+            parse_input(String::from("sh -c 'kill -STOP $$'")).unwrap_or_else(|e| panic!("{}", e));
+        for cmd in command.cmds.into_iter() {
+            handle_command_with_output(cmd, false, &mut state).unwrap_or_else(|e| panic!("{}", e));
+        }
+        assert_eq!(state.exit_status, 148);
+        assert_eq!(state.jobs.len(), 1);
+        assert_eq!(state.jobs[0].status(), JobStatus::Stopped);
+        kill_jobs(&mut state);
+    }
+
+    #[test]
+    fn if_runs_the_first_branch_whose_condition_succeeds() {
+        let mut state = State::for_tests();
+        let line = "if false; then echo a; elif true; then echo b; else echo c; fi";
+        assert_eq!(output(line, &mut state), "b");
+        assert_eq!(
+            output("if false; then echo a; else echo c; fi", &mut state),
+            "c"
+        );
+        assert_eq!(
+            output("if true\nthen\n  echo x\n  echo y\nfi", &mut state),
+            "x y"
+        );
+    }
+
+    #[test]
+    fn exit_status_of_if() {
+        let mut state = State::for_tests();
+        assert_eq!(
+            output("if false; then echo a; fi; echo $?", &mut state),
+            "0"
+        );
+        let line = "if false; then true; else sh -c 'exit 4'; fi; echo $?";
+        assert_eq!(output(line, &mut state), "4");
+    }
+
+    #[test]
+    fn for_loops_over_the_words() {
+        let mut state = State::for_tests();
+        assert_eq!(
+            output("for i in a b c; do echo $i; done", &mut state),
+            "a b c"
+        );
+        assert_eq!(
+            output("for i in; do echo $i; done; echo $?", &mut state),
+            "0"
+        );
+    }
+
+    #[test]
+    fn while_and_until_check_their_condition_first() {
+        let mut state = State::for_tests();
+        assert_eq!(
+            output("while false; do echo x; done; echo $?", &mut state),
+            "0"
+        );
+        assert_eq!(output("until true; do echo x; done", &mut state), "");
+    }
+
+    #[test]
+    fn break_and_continue_leave_nested_loops() {
+        let mut state = State::for_tests();
+        let line = "for i in 1 2 3; do for j in a b; do \
+                    if test $i = 2; then continue 2; fi; \
+                    if test $i$j = 3b; then break 2; fi; \
+                    echo $i$j; done; done";
+        assert_eq!(output(line, &mut state), "1a 1b 3a");
+        let line = "while true; do echo once; break; done";
+        assert_eq!(output(line, &mut state), "once");
+    }
+
+    #[test]
+    fn case_runs_the_first_matching_pattern() {
+        let mut state = State::for_tests();
+        let line = "case abc in a|b) echo 1;; a*) echo 2;; *) echo 3;; esac";
+        assert_eq!(output(line, &mut state), "2");
+        let line = "case b in\n  a) echo 1;;\n  b | c) echo 2\n    echo 3;;\nesac";
+        assert_eq!(output(line, &mut state), "2 3");
+        assert_eq!(
+            output("case x in y) echo 1;; esac; echo $?", &mut state),
+            "0"
+        );
+        assert_eq!(
+            output("case a.rs in [a-c].?s) echo yes;; esac", &mut state),
+            "yes"
+        );
+    }
+
+    #[test]
+    fn functions_get_positional_parameters() {
+        let mut state = State::for_tests();
+        let line = "f() { echo $# $1 $2; return 3; }; f a b; echo $?";
+        assert_eq!(output(line, &mut state), "2 a b 3");
+        assert!(state.positional_args.is_empty());
+    }
+
+    #[test]
+    fn local_variables_are_restored_when_the_function_returns() {
+        let mut state = State::for_tests();
+        let line = "g() { local v=in; echo $v; }; g; echo -$v-";
+        assert_eq!(output(line, &mut state), "in --");
+    }
+
+    #[test]
+    fn exported_variables_are_passed_on_to_programs() {
+        let mut state = State::for_tests();
+        let line = "export VRSH_TEST_EXPORTED=yes; sh -c 'echo $VRSH_TEST_EXPORTED'";
+        assert_eq!(output(line, &mut state), "yes");
+        let line = "unset VRSH_TEST_EXPORTED; sh -c 'echo [$VRSH_TEST_EXPORTED]'";
+        assert_eq!(output(line, &mut state), "[]");
+    }
+
+    #[test]
+    fn variables_are_read_from_the_environment() {
+        let mut state = State::for_tests();
+        let path = std::env::var("PATH").unwrap_or_default();
+        assert_eq!(output("echo $PATH", &mut state), path);
+        assert_eq!(output("unset PATH; /bin/echo -$PATH-", &mut state), "--");
+    }
+
+    #[test]
+    fn readonly_variables_cant_be_changed() {
+        let mut state = State::for_tests();
+        let line = "readonly VRSH_TEST_R=1; export VRSH_TEST_R=2; echo $? $VRSH_TEST_R";
+        assert_eq!(output(line, &mut state), "1 1");
+    }
+
+    #[test]
+    fn assignments_before_a_program_only_apply_to_it() {
+        let mut state = State::for_tests();
+        let line = "VRSH_TEST_P=1 sh -c 'echo $VRSH_TEST_P'; echo -$VRSH_TEST_P-";
+        assert_eq!(output(line, &mut state), "1 --");
+    }
+
+    #[test]
+    fn assignments_set_shell_variables() {
+        let mut state = State::for_tests();
+        assert_eq!(output("a=b; echo $a", &mut state), "b");
+        assert_eq!(output("a=1 b=$a; echo $b", &mut state), "1");
+        assert_eq!(output("sh -c 'echo [$a]'", &mut state), "[]");
+    }
+
+    #[test]
+    fn here_documents_are_the_input_of_the_command() {
+        let mut state = State::for_tests();
+        assert_eq!(
+            output("x=1\ncat <<EOF\na $x\n  b\nEOF", &mut state),
+            "a 1   b"
+        );
+        assert_eq!(output("cat <<'EOF'\n$x\nEOF", &mut state), "$x");
+        assert_eq!(output("cat <<-EOF\n\tc\n\tEOF", &mut state), "c");
+        assert_eq!(output("cat <<< h$x", &mut state), "h1");
+    }
+
+    #[test]
+    fn redirects_apply_in_order() {
+        let mut state = State::for_tests();
+        let dir = temp_dir("redirects");
+        let line = format!("echo a > {0}/o; echo b >> {0}/o; cat {0}/o", dir);
+        assert_eq!(output(&line, &mut state), "a b");
+        assert_eq!(output("sh -c 'echo e >&2' 2>&1", &mut state), "e");
+        let line = format!(
+            "sh -c 'echo out; echo err >&2' 2>&1 > {0}/f; cat {0}/f",
+            dir
+        );
+        assert_eq!(output(&line, &mut state), "err out");
+        let line = format!("sh -c 'echo x; echo y >&2' &> {0}/g; cat {0}/g", dir);
+        assert_eq!(output(&line, &mut state), "x y");
+        let line = format!("sh -c 'echo z >&2' 3>&1 2>{0}/h 1>&-; cat {0}/h", dir);
+        assert_eq!(output(&line, &mut state), "z");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn pipe_with_stderr() {
+        let mut state = State::for_tests();
+        assert_eq!(output("sh -c 'echo z >&2' |& cat", &mut state), "z");
+        assert_eq!(
+            output("if true; then sh -c 'echo z >&2'; fi |& cat", &mut state),
+            "z"
+        );
+    }
+
+    #[test]
+    fn noclobber_keeps_existing_files() {
+        let mut state = State::for_tests();
+        let dir = temp_dir("noclobber");
+        let line = format!(
+            "set -o noclobber; echo a > {0}/f; echo b > {0}/f; echo $?; cat {0}/f",
+            dir
+        );
+        assert_eq!(output(&line, &mut state), "1 a");
+        let line = format!("echo c >| {0}/f; echo d >> {0}/f; cat {0}/f", dir);
+        assert_eq!(output(&line, &mut state), "c d");
+        let line = format!("set +o noclobber; echo e > {0}/f; cat {0}/f", dir);
+        assert_eq!(output(&line, &mut state), "e");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn process_substitutions_are_paths() {
+        let mut state = State::for_tests();
+        assert_eq!(output("cat <(echo a) <(echo b)", &mut state), "a b");
+        assert!(output("echo <(true)", &mut state).starts_with("/dev/fd/"));
+    }
+
+    #[test]
+    fn subshells_dont_change_the_shell() {
+        let mut state = State::for_tests();
+        assert_eq!(output("a=1; (a=2; echo $a); echo $a", &mut state), "2 1");
+        assert_eq!(output("(exit 3); echo $?", &mut state), "3");
+    }
+
+    #[test]
+    fn groups_run_in_the_shell() {
+        let mut state = State::for_tests();
+        assert_eq!(output("{ a=3; echo a; }; echo $a", &mut state), "a 3");
+        assert_eq!(output("{ echo a; echo b; } | cat", &mut state), "a b");
+        let dir = temp_dir("groups");
+        let line = format!("{{ echo a; echo b; }} > {0}/o; cat {0}/o", dir);
+        assert_eq!(output(&line, &mut state), "a b");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn words_with_equals_are_globbed_unless_assigned() {
+        let mut state = State::for_tests();
+        let dir = temp_dir("equals-globs");
+        fs::write(format!("{}/a=1.txt", dir), "").unwrap();
+        fs::write(format!("{}/--include=x.rs", dir), "").unwrap();
+        let line = format!(
+            "(cd {}; echo a=*.txt --include=*.rs; export a=*.txt; echo \"$a\")",
+            dir
+        );
+        assert_eq!(output(&line, &mut state), "a=1.txt --include=x.rs *.txt");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn built_ins_in_a_pipeline_dont_change_the_shell() {
+        let mut state = State::for_tests();
+        assert_eq!(output("a=1; a=2 | true; echo $a", &mut state), "1");
+        assert_eq!(output("f() { echo x; }; f | cat", &mut state), "x");
+    }
+
+    #[test]
+    fn assignments_before_a_function_are_exported_while_it_runs() {
+        let mut state = State::for_tests();
+        let line = "f() { sh -c 'echo $VRSH_TEST_F'; }; VRSH_TEST_F=1 f; echo \"[$VRSH_TEST_F]\"";
+        assert_eq!(output(line, &mut state), "1 []");
+    }
+
+    #[test]
+    fn and_or_list_in_the_background_is_one_job() {
+        let mut state = State::for_tests();
+        assert_eq!(output("false || sh -c 'exit 5' &", &mut state), "");
+        assert_eq!(state.jobs.len(), 1);
+        assert_eq!(output("wait %1; echo $?", &mut state), "5");
+    }
+
+    #[test]
+    fn output_of_built_ins_is_captured() {
+        let mut state = State::for_tests();
+        let exported = output("export VRSH_TEST_C=1; export -p", &mut state);
+        assert!(exported.contains("export VRSH_TEST_C='1'"));
+        assert!(output("env", &mut state).contains("VRSH_TEST_C=1"));
+        let line = "readonly VRSH_TEST_D=2; readonly";
+        assert_eq!(output(line, &mut state), "readonly VRSH_TEST_D='2'");
+        assert!(output("set -o", &mut state).contains("noclobber\toff"));
+        assert_eq!(output("shopt nullglob", &mut state), "nullglob\toff");
+    }
+
+    #[test]
+    fn redirects_of_built_ins_and_functions() {
+        let mut state = State::for_tests();
+        let dir = temp_dir("built-in-redirects");
+        let line = format!("shopt nullglob > {0}/o; cat {0}/o", dir);
+        assert_eq!(output(&line, &mut state), "nullglob\toff");
+        let line = format!(
+            "export VRSH_TEST_E=1; env > {0}/e; grep VRSH_TEST_E {0}/e",
+            dir
+        );
+        assert_eq!(output(&line, &mut state), "VRSH_TEST_E=1");
+        let line = format!("cd /vrsh-no-such-dir 2> {0}/c; echo $?; cat {0}/c", dir);
+        assert!(output(&line, &mut state).starts_with("1 vrsh: "));
+        let line = format!(
+            "f() {{ echo a; shopt nullglob; }}; f > {0}/f; cat {0}/f",
+            dir
+        );
+        assert_eq!(output(&line, &mut state), "a nullglob\toff");
+        fs::remove_dir_all(&dir).unwrap();
     }
 }
@@ -0,0 +1,741 @@
+use crate::shell::common::types::HereDoc;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::iter::Peekable;
+use std::str::CharIndices;
+
+pub type Spanned<Tok, Loc, Error> = Result<(Loc, Tok, Loc), Error>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Tok {
+    Word(String),
+    // A `NAME=value` word before the command, e.g. `FOO=bar cmd`.
+    Assignment(String),
+    Pipe,
+    // `|&`, also pipes stderr.
+    PipeAll,
+    And,
+    Or,
+    Ampersand,
+    Semicolon,
+    Newline,
+    // The file descriptor number right before a redirect, e.g. the `2` in `2> file`.
+    IoNumber(i32),
+    RedirectIn,
+    RedirectOut,
+    RedirectClobber,
+    RedirectAppend,
+    DuplicateIn,
+    DuplicateOut,
+    RedirectAll,
+    AppendAll,
+    HereDoc(HereDoc),
+    HereString,
+    If,
+    Then,
+    Elif,
+    Else,
+    Fi,
+    While,
+    Until,
+    For,
+    In,
+    Do,
+    Done,
+    Case,
+    Esac,
+    DoubleSemicolon,
+    LeftParen,
+    RightParen,
+    LeftBrace,
+    RightBrace,
+}
+
+impl Tok {
+    /// Whether the token can be followed by a new command,
+    /// reserved words are only recognised at the start of a command.
+    fn starts_command(&self) -> bool {
+        matches!(
+            self,
+            Tok::Pipe
+                | Tok::PipeAll
+                | Tok::And
+                | Tok::Or
+                | Tok::Ampersand
+                | Tok::Semicolon
+                | Tok::Newline
+                | Tok::If
+                | Tok::Then
+                | Tok::Elif
+                | Tok::Else
+                | Tok::While
+                | Tok::Until
+                | Tok::Do
+                | Tok::LeftParen
+                | Tok::RightParen
+                | Tok::LeftBrace
+        )
+    }
+
+    /// Whether the token is a redirect operator, which has to be followed by its target.
+    pub fn is_redirect(&self) -> bool {
+        matches!(
+            self,
+            Tok::IoNumber(_)
+                | Tok::RedirectIn
+                | Tok::RedirectOut
+                | Tok::RedirectClobber
+                | Tok::RedirectAppend
+                | Tok::DuplicateIn
+                | Tok::DuplicateOut
+                | Tok::RedirectAll
+                | Tok::AppendAll
+                | Tok::HereString
+        )
+    }
+}
+
+/// Whether the word has the form `NAME=value`.
+fn is_assignment(word: &str) -> bool {
+    match word.split_once('=') {
+        Some((name, _)) => {
+            let mut chars = name.chars();
+            matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+                && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        None => false,
+    }
+}
+
+/// Removes the `\` and newline joining the lines of a word, they're kept within single quotes.
+fn join_lines(word: &str) -> String {
+    let mut joined = String::new();
+    let mut single_quoted = false;
+    let mut double_quoted = false;
+    // Within `$'...'`, where `\` still escapes the next character.
+    let mut ansi_c_quoted = false;
+    let mut chars = word.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' if ansi_c_quoted => ansi_c_quoted = false,
+            '\'' if !double_quoted => single_quoted = !single_quoted,
+            '$' if !single_quoted && !double_quoted && chars.peek() == Some(&'\'') => {
+                chars.next();
+                joined.push_str("$'");
+                ansi_c_quoted = true;
+                continue;
+            }
+            '"' if !single_quoted && !ansi_c_quoted => double_quoted = !double_quoted,
+            '\\' if !single_quoted => match chars.next() {
+                Some('\n') if !ansi_c_quoted => continue,
+                Some(escaped) => {
+                    joined.push(c);
+                    joined.push(escaped);
+                    continue;
+                }
+                None => {}
+            },
+            _ => {}
+        }
+        joined.push(c);
+    }
+    joined
+}
+
+fn reserved_word(word: &str) -> Option<Tok> {
+    match word {
+        "if" => Some(Tok::If),
+        "then" => Some(Tok::Then),
+        "elif" => Some(Tok::Elif),
+        "else" => Some(Tok::Else),
+        "fi" => Some(Tok::Fi),
+        "while" => Some(Tok::While),
+        "until" => Some(Tok::Until),
+        "for" => Some(Tok::For),
+        "do" => Some(Tok::Do),
+        "done" => Some(Tok::Done),
+        "case" => Some(Tok::Case),
+        "esac" => Some(Tok::Esac),
+        "{" => Some(Tok::LeftBrace),
+        "}" => Some(Tok::RightBrace),
+        _ => None,
+    }
+}
+
+impl Display for Tok {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Tok::Word(w) => write!(f, "{}", w),
+            Tok::Assignment(w) => write!(f, "{}", w),
+            Tok::Pipe => write!(f, "|"),
+            Tok::PipeAll => write!(f, "|&"),
+            Tok::And => write!(f, "&&"),
+            Tok::Or => write!(f, "||"),
+            Tok::Ampersand => write!(f, "&"),
+            Tok::Semicolon => write!(f, ";"),
+            Tok::Newline => write!(f, "newline"),
+            Tok::IoNumber(n) => write!(f, "{}", n),
+            Tok::RedirectIn => write!(f, "<"),
+            Tok::RedirectOut => write!(f, ">"),
+            Tok::RedirectClobber => write!(f, ">|"),
+            Tok::RedirectAppend => write!(f, ">>"),
+            Tok::DuplicateIn => write!(f, "<&"),
+            Tok::DuplicateOut => write!(f, ">&"),
+            Tok::RedirectAll => write!(f, "&>"),
+            Tok::AppendAll => write!(f, "&>>"),
+            Tok::HereDoc(doc) => write!(f, "{}", doc),
+            Tok::HereString => write!(f, "<<<"),
+            Tok::If => write!(f, "if"),
+            Tok::Then => write!(f, "then"),
+            Tok::Elif => write!(f, "elif"),
+            Tok::Else => write!(f, "else"),
+            Tok::Fi => write!(f, "fi"),
+            Tok::While => write!(f, "while"),
+            Tok::Until => write!(f, "until"),
+            Tok::For => write!(f, "for"),
+            Tok::In => write!(f, "in"),
+            Tok::Do => write!(f, "do"),
+            Tok::Done => write!(f, "done"),
+            Tok::Case => write!(f, "case"),
+            Tok::Esac => write!(f, "esac"),
+            Tok::DoubleSemicolon => write!(f, ";;"),
+            Tok::LeftParen => write!(f, "("),
+            Tok::RightParen => write!(f, ")"),
+            Tok::LeftBrace => write!(f, "{{"),
+            Tok::RightBrace => write!(f, "}}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+#[allow(clippy::enum_variant_names)]
+pub enum LexError {
+    UnterminatedQuote(char),
+    UnterminatedSubstitution,
+    UnterminatedParameter,
+    UnterminatedHereDoc(String),
+    MissingDelimiter,
+    // A `\` at the end of the input, the command continues on the next line.
+    TrailingBackslash,
+}
+
+impl Display for LexError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            LexError::UnterminatedQuote(q) => write!(f, "missing closing {}", q),
+            LexError::UnterminatedSubstitution => write!(f, "missing closing ) for $("),
+            LexError::UnterminatedParameter => write!(f, "missing closing }} for ${{"),
+            LexError::UnterminatedHereDoc(delimiter) => {
+                write!(f, "missing here-document delimiter '{}'", delimiter)
+            }
+            LexError::MissingDelimiter => write!(f, "missing here-document delimiter after <<"),
+            LexError::TrailingBackslash => write!(f, "missing line after \\"),
+        }
+    }
+}
+
+/// Splits the input into the tokens used by the command grammar.
+/// Words are kept exactly as written (quotes, `$( )` etc. included),
+/// they are expanded first when the command they belong to is executed.
+pub struct Lexer<'input> {
+    input: &'input str,
+    chars: Peekable<CharIndices<'input>>,
+    command_start: bool,
+    // Whether a `NAME=value` word would be an assignment, at the start of a command or after another assignment.
+    assignment_allowed: bool,
+    // The `for` or `case` the next `in` belongs to and the number of tokens read since,
+    // `in` is only a reserved word right after the name (or word) following them.
+    in_expected: Option<(Tok, usize)>,
+    // Whether the patterns of a `case` item are being read, `esac` is the only reserved word there.
+    case_pattern: bool,
+    // Where the bodies of the here-documents on the current line end, the lexer continues from there after the line.
+    here_doc_end: Option<usize>,
+}
+
+impl<'input> Lexer<'input> {
+    pub fn new(input: &'input str) -> Self {
+        Lexer {
+            input,
+            chars: input.char_indices().peekable(),
+            command_start: true,
+            assignment_allowed: true,
+            in_expected: None,
+            case_pattern: false,
+            here_doc_end: None,
+        }
+    }
+
+    fn pos(&mut self) -> usize {
+        match self.chars.peek() {
+            Some((i, _)) => *i,
+            None => self.input.len(),
+        }
+    }
+
+    fn operator(&mut self, start: usize, tok: Tok, len: usize) -> Spanned<Tok, usize, LexError> {
+        for _ in 0..len {
+            self.chars.next();
+        }
+        Ok((start, tok, self.pos()))
+    }
+
+    /// Whether the remaining input starts with the operator.
+    fn at(&mut self, operator: &str) -> bool {
+        let pos = self.pos();
+        self.input[pos..].starts_with(operator)
+    }
+
+    fn next_is(&mut self, c: char) -> bool {
+        let mut ahead = self.chars.clone();
+        ahead.next();
+        matches!(ahead.peek(), Some((_, n)) if *n == c)
+    }
+
+    fn word(&mut self, start: usize) -> Spanned<Tok, usize, LexError> {
+        self.scan_word()?;
+        let end = self.pos();
+        let word = &join_lines(&self.input[start..end]);
+        // A number directly followed by a redirect is the file descriptor to redirect.
+        if word.chars().all(|c| c.is_ascii_digit()) && (self.at("<") || self.at(">")) {
+            if let Ok(fd) = word.parse() {
+                return Ok((start, Tok::IoNumber(fd), end));
+            }
+        }
+        let tok = match reserved_word(word) {
+            // `esac` can still be used as a pattern, e.g. `case $a in esac) ...`.
+            Some(Tok::Esac) if self.case_pattern && !self.pattern_follows() => Tok::Esac,
+            _ if self.case_pattern => Tok::Word(String::from(word)),
+            _ if self.assignment_allowed && is_assignment(word) => {
+                Tok::Assignment(String::from(word))
+            }
+            Some(tok) if self.command_start => tok,
+            _ if word == "in" && matches!(self.in_expected, Some((_, 1))) => Tok::In,
+            _ => Tok::Word(String::from(word)),
+        };
+        Ok((start, tok, end))
+    }
+
+    /// Consumes the characters of a word, quotes and substitutions included.
+    fn scan_word(&mut self) -> Result<(), LexError> {
+        while let Some(&(_, c)) = self.chars.peek() {
+            match c {
+                // Process substitutions, `<(cmd)` and `>(cmd)`, are part of the word.
+                '<' | '>' if self.next_is('(') => {
+                    self.chars.next();
+                    self.chars.next();
+                    self.substitution()?;
+                }
+                ' ' | '\t' | '\r' | '\n' | ';' | '&' | '|' | '<' | '>' | '(' | ')' => break,
+                '\'' => {
+                    self.chars.next();
+                    self.single_quoted()?;
+                }
+                '"' => {
+                    self.chars.next();
+                    self.double_quoted()?;
+                }
+                '$' => {
+                    self.chars.next();
+                    if let Some((_, '\'')) = self.chars.peek() {
+                        self.chars.next();
+                        self.ansi_c_quoted()?;
+                    } else {
+                        self.dollar()?;
+                    }
+                }
+                '\\' => {
+                    self.chars.next();
+                    // The escaped character is part of the word, an escaped newline continues it on the next line.
+                    if self.chars.next().is_none() {
+                        return Err(LexError::TrailingBackslash);
+                    }
+                }
+                _ => {
+                    self.chars.next();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads a `<<<`, or a `<<` (or `<<-`) together with its delimiter and the body following the current line.
+    fn here_doc(&mut self, start: usize) -> Spanned<Tok, usize, LexError> {
+        self.chars.next();
+        self.chars.next();
+        if let Some((_, '<')) = self.chars.peek() {
+            self.chars.next();
+            return Ok((start, Tok::HereString, self.pos()));
+        }
+        let strip_tabs = matches!(self.chars.peek(), Some((_, '-')));
+        if strip_tabs {
+            self.chars.next();
+        }
+        while let Some((_, ' ')) | Some((_, '\t')) = self.chars.peek() {
+            self.chars.next();
+        }
+
+        let delimiter_start = self.pos();
+        self.scan_word()?;
+        let end = self.pos();
+        let delimiter = &self.input[delimiter_start..end];
+        if delimiter.is_empty() {
+            return Err(LexError::MissingDelimiter);
+        }
+        // Quoting any part of the delimiter disables expansions in the body.
+        let expand = !delimiter.contains(['\'', '"', '\\']);
+        let unquoted = delimiter.replace(['\'', '"', '\\'], "");
+
+        // Several here-documents on the same line follow each other.
+        let body_start = match self.here_doc_end {
+            Some(body_start) => body_start,
+            None => match self.input[end..].find('\n') {
+                Some(i) => end + i + 1,
+                None => return Err(LexError::UnterminatedHereDoc(unquoted)),
+            },
+        };
+        let mut body = String::new();
+        let mut body_end = body_start;
+        for line in self.input[body_start..].split_inclusive('\n') {
+            body_end += line.len();
+            let line = if strip_tabs {
+                line.trim_start_matches('\t')
+            } else {
+                line
+            };
+            if line.strip_suffix('\n').unwrap_or(line) == unquoted {
+                self.here_doc_end = Some(body_end);
+                let doc = HereDoc {
+                    delimiter: delimiter.to_string(),
+                    strip_tabs,
+                    expand,
+                    body,
+                };
+                return Ok((start, Tok::HereDoc(doc), end));
+            }
+            body += line;
+        }
+        Err(LexError::UnterminatedHereDoc(unquoted))
+    }
+
+    /// Whether the next token ends a pattern (`)` or `|`).
+    fn pattern_follows(&mut self) -> bool {
+        let next = self
+            .chars
+            .clone()
+            .map(|(_, c)| c)
+            .find(|c| *c != ' ' && *c != '\t');
+        matches!(next, Some(')') | Some('|'))
+    }
+
+    fn single_quoted(&mut self) -> Result<(), LexError> {
+        for (_, c) in self.chars.by_ref() {
+            if c == '\'' {
+                return Ok(());
+            }
+        }
+        Err(LexError::UnterminatedQuote('\''))
+    }
+
+    fn double_quoted(&mut self) -> Result<(), LexError> {
+        while let Some((_, c)) = self.chars.next() {
+            match c {
+                '"' => return Ok(()),
+                '$' => self.dollar()?,
+                '\\' => {
+                    self.chars.next();
+                }
+                _ => {}
+            }
+        }
+        Err(LexError::UnterminatedQuote('"'))
+    }
+
+    /// Consumes the rest of a `$'...'` string, where `\` escapes the next character.
+    fn ansi_c_quoted(&mut self) -> Result<(), LexError> {
+        while let Some((_, c)) = self.chars.next() {
+            match c {
+                '\'' => return Ok(()),
+                '\\' => {
+                    self.chars.next();
+                }
+                _ => {}
+            }
+        }
+        Err(LexError::UnterminatedQuote('\''))
+    }
+
+    /// Consumes a `$( )` or `${ }` following a `$` (which has already been consumed).
+    fn dollar(&mut self) -> Result<(), LexError> {
+        match self.chars.peek() {
+            Some((_, '(')) => {
+                self.chars.next();
+                self.substitution()
+            }
+            Some((_, '{')) => {
+                self.chars.next();
+                self.parameter()
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Consumes the rest of a `${ }` (the `${` has already been consumed), including nested expansions.
+    fn parameter(&mut self) -> Result<(), LexError> {
+        while let Some((_, c)) = self.chars.next() {
+            match c {
+                '\'' => self.single_quoted()?,
+                '"' => self.double_quoted()?,
+                '$' => self.dollar()?,
+                '\\' => {
+                    self.chars.next();
+                }
+                '}' => return Ok(()),
+                _ => {}
+            }
+        }
+        Err(LexError::UnterminatedParameter)
+    }
+
+    /// Consumes the rest of a `$( )` (the `$(` has already been consumed).
+    fn substitution(&mut self) -> Result<(), LexError> {
+        let mut depth = 1;
+        while let Some((_, c)) = self.chars.next() {
+            match c {
+                '\'' => self.single_quoted()?,
+                '"' => self.double_quoted()?,
+                '\\' => {
+                    self.chars.next();
+                }
+                '(' => depth += 1,
+                ')' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Ok(());
+                    }
+                }
+                _ => {}
+            }
+        }
+        Err(LexError::UnterminatedSubstitution)
+    }
+
+    fn token(&mut self) -> Option<Spanned<Tok, usize, LexError>> {
+        loop {
+            let (start, c) = *self.chars.peek()?;
+            return Some(match c {
+                ' ' | '\t' | '\r' => {
+                    self.chars.next();
+                    continue;
+                }
+                '#' => {
+                    // Comments last until the end of the line.
+                    while let Some((_, c)) = self.chars.peek() {
+                        if *c == '\n' {
+                            break;
+                        }
+                        self.chars.next();
+                    }
+                    continue;
+                }
+                // A line ending with `\` continues on the next line.
+                '\\' if self.next_is('\n') => {
+                    self.chars.next();
+                    self.chars.next();
+                    continue;
+                }
+                '\n' => {
+                    let tok = self.operator(start, Tok::Newline, 1);
+                    // Skip the bodies of the here-documents on the line.
+                    if let Some(end) = self.here_doc_end.take() {
+                        while self.pos() < end {
+                            self.chars.next();
+                        }
+                    }
+                    tok
+                }
+                ';' if self.next_is(';') => self.operator(start, Tok::DoubleSemicolon, 2),
+                ';' => self.operator(start, Tok::Semicolon, 1),
+                '|' if self.next_is('|') => self.operator(start, Tok::Or, 2),
+                '|' if self.next_is('&') => self.operator(start, Tok::PipeAll, 2),
+                '|' => self.operator(start, Tok::Pipe, 1),
+                '&' if self.next_is('&') => self.operator(start, Tok::And, 2),
+                '&' if self.at("&>>") => self.operator(start, Tok::AppendAll, 3),
+                '&' if self.next_is('>') => self.operator(start, Tok::RedirectAll, 2),
+                '&' => self.operator(start, Tok::Ampersand, 1),
+                '<' | '>' if self.next_is('(') => self.word(start),
+                '<' if self.next_is('<') => self.here_doc(start),
+                '<' if self.next_is('&') => self.operator(start, Tok::DuplicateIn, 2),
+                '<' => self.operator(start, Tok::RedirectIn, 1),
+                '>' if self.next_is('|') => self.operator(start, Tok::RedirectClobber, 2),
+                '>' if self.next_is('>') => self.operator(start, Tok::RedirectAppend, 2),
+                '>' if self.next_is('&') => self.operator(start, Tok::DuplicateOut, 2),
+                '>' => self.operator(start, Tok::RedirectOut, 1),
+                '(' => self.operator(start, Tok::LeftParen, 1),
+                ')' => self.operator(start, Tok::RightParen, 1),
+                _ => self.word(start),
+            });
+        }
+    }
+}
+
+impl<'input> Iterator for Lexer<'input> {
+    type Item = Spanned<Tok, usize, LexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let token = self.token()?;
+        if let Ok((_, tok, _)) = &token {
+            self.command_start = tok.starts_command();
+            self.assignment_allowed = self.command_start || matches!(tok, Tok::Assignment(_));
+            self.case_pattern = match tok {
+                Tok::In => matches!(self.in_expected, Some((Tok::Case, _))),
+                Tok::DoubleSemicolon => true,
+                Tok::RightParen | Tok::Esac => false,
+                _ => self.case_pattern,
+            };
+            self.in_expected = match tok {
+                Tok::For | Tok::Case => Some((tok.clone(), 0)),
+                _ => self.in_expected.take().map(|(keyword, n)| (keyword, n + 1)),
+            };
+        }
+        Some(token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(input: &str) -> Vec<Tok> {
+        Lexer::new(input)
+            .map(|t| t.map(|(_, tok, _)| tok).unwrap_or_else(|e| panic!("{}", e)))
+            .collect()
+    }
+
+    fn word(w: &str) -> Tok {
+        Tok::Word(w.to_string())
+    }
+
+    #[test]
+    fn separators_end_words_outside_of_quotes() {
+        assert_eq!(
+            tokens("echo 'a;b' \"c\nd\" $(x; y);ls\n# c; d\npwd"),
+            vec![
+                word("echo"),
+                word("'a;b'"),
+                word("\"c\nd\""),
+                word("$(x; y)"),
+                Tok::Semicolon,
+                word("ls"),
+                Tok::Newline,
+                Tok::Newline,
+                word("pwd"),
+            ]
+        );
+    }
+
+    #[test]
+    fn unterminated_quote_is_an_error() {
+        let result: Result<Vec<_>, _> = Lexer::new("echo 'a; b").collect();
+        assert!(matches!(result, Err(LexError::UnterminatedQuote('\''))));
+    }
+
+    #[test]
+    fn reserved_words_start_commands() {
+        assert_eq!(
+            tokens("if true; then echo if fi; fi"),
+            vec![
+                Tok::If,
+                word("true"),
+                Tok::Semicolon,
+                Tok::Then,
+                word("echo"),
+                word("if"),
+                word("fi"),
+                Tok::Semicolon,
+                Tok::Fi,
+            ]
+        );
+    }
+
+    #[test]
+    fn quoted_reserved_words_are_words() {
+        assert_eq!(
+            tokens("'if' \"then\""),
+            vec![word("'if'"), word("\"then\"")]
+        );
+    }
+
+    #[test]
+    fn in_follows_the_name_of_a_for_loop() {
+        assert_eq!(
+            tokens("for in in in; do echo in; done"),
+            vec![
+                Tok::For,
+                word("in"),
+                Tok::In,
+                word("in"),
+                Tok::Semicolon,
+                Tok::Do,
+                word("echo"),
+                word("in"),
+                Tok::Semicolon,
+                Tok::Done,
+            ]
+        );
+    }
+
+    #[test]
+    fn here_doc_reads_the_lines_after_the_command() {
+        let doc = HereDoc {
+            delimiter: String::from("EOF"),
+            strip_tabs: false,
+            expand: true,
+            body: String::from("a $x\n  b\n"),
+        };
+        assert_eq!(
+            tokens("cat <<EOF; echo c\na $x\n  b\nEOF\necho d"),
+            vec![
+                word("cat"),
+                Tok::HereDoc(doc),
+                Tok::Semicolon,
+                word("echo"),
+                word("c"),
+                Tok::Newline,
+                word("echo"),
+                word("d"),
+            ]
+        );
+    }
+
+    #[test]
+    fn here_doc_with_quoted_delimiter_and_stripped_tabs() {
+        let doc = HereDoc {
+            delimiter: String::from("'E'"),
+            strip_tabs: true,
+            expand: false,
+            body: String::from("$x\n"),
+        };
+        assert_eq!(
+            tokens("cat <<-'E'\n\t$x\n\tE\n"),
+            vec![word("cat"), Tok::HereDoc(doc), Tok::Newline]
+        );
+    }
+
+    #[test]
+    fn here_docs_on_the_same_line_follow_each_other() {
+        let bodies: Vec<String> = tokens("cat <<A <<B\n1\nA\n2\nB\n")
+            .into_iter()
+            .filter_map(|tok| match tok {
+                Tok::HereDoc(doc) => Some(doc.body),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(bodies, vec!["1\n", "2\n"]);
+    }
+
+    #[test]
+    fn unterminated_here_doc_is_an_error() {
+        let result: Result<Vec<_>, _> = Lexer::new("cat <<EOF\nbody\n").collect();
+        assert!(matches!(result, Err(LexError::UnterminatedHereDoc(d)) if d == "EOF"));
+    }
+}
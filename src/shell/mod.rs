@@ -1,5 +1,7 @@
 pub mod colors;
+pub mod expand_parameter;
 pub mod handle_command;
+pub mod lexer;
 pub mod parse_command;
 pub mod rl_helper;
 
@@ -1,20 +1,34 @@
 use std::fmt;
 use std::fmt::{Display, Formatter};
 use std::io::Error;
+use std::iter::Peekable;
+use std::os::unix::io::AsRawFd;
+use std::str::CharIndices;
 
-use crate::expansions::InitialCmdOrCommentParser;
-use crate::grammar::CommandParser;
-use crate::replacements::ReplacementCmdParser;
-use crate::shell::common::state::State;
-use crate::shell::common::types::{
-    Cmd, InitialCmd, InitialCmdOrComment, InitialCmdPart, ReplacementPart, ReplacementsCmd,
-};
-use crate::shell::handle_command::{handle_sub_command, CommandError};
+use crate::arithmetic::ArithmeticParser;
+use crate::grammar::CommandListParser;
+use crate::shell::built_ins::variables::is_valid_identifier;
+use crate::shell::common::arithmetic::{evaluate, ArithmeticError};
+use crate::shell::common::braces::expand_braces;
+use crate::shell::common::glob::expand_glob;
+use crate::shell::common::state::{State, StateError};
+use crate::shell::common::types::{Arg, Assignment, CmdList, CmdPart, Redirect};
+use crate::shell::expand_parameter::{expand_parameter, read_var};
+use crate::shell::handle_command::{handle_process_substitution, handle_sub_command, CommandError};
+use crate::shell::lexer::{LexError, Lexer};
 
 pub enum ParseError {
     IO(std::io::Error),
     LALRPopErr(String, String),
     EvaluationError(CommandError),
+    LexError(LexError),
+    AmbiguousRedirect(String),
+    BadSubstitution(String),
+    ParameterError(String, String),
+    StateError(StateError),
+    ArithmeticError(ArithmeticError),
+    NoMatch(String),
+    Incomplete,
     Comment,
     InputEmpty,
 }
@@ -29,6 +43,14 @@ impl Display for ParseError {
             ParseError::EvaluationError(cmd_err) => {
                 write!(f, "failed to evaluate command: {}", cmd_err)
             }
+            ParseError::LexError(e) => write!(f, "{}", e),
+            ParseError::AmbiguousRedirect(word) => write!(f, "ambiguous redirect '{}'", word),
+            ParseError::BadSubstitution(parameter) => write!(f, "bad substitution '{}'", parameter),
+            ParseError::ParameterError(name, message) => write!(f, "{}: {}", name, message),
+            ParseError::StateError(e) => write!(f, "{}", e),
+            ParseError::ArithmeticError(e) => write!(f, "arithmetic error: {}", e),
+            ParseError::NoMatch(pattern) => write!(f, "no match: {}", pattern),
+            ParseError::Incomplete => write!(f, "unexpected end of input"),
             ParseError::Comment => write!(f, "comment encountered, ignore"),
             ParseError::InputEmpty => write!(f, "input empty, ignore"),
         }
@@ -41,6 +63,24 @@ impl From<std::io::Error> for ParseError {
     }
 }
 
+impl From<StateError> for ParseError {
+    fn from(err: StateError) -> Self {
+        ParseError::StateError(err)
+    }
+}
+
+impl From<ArithmeticError> for ParseError {
+    fn from(err: ArithmeticError) -> Self {
+        ParseError::ArithmeticError(err)
+    }
+}
+
+impl From<LexError> for ParseError {
+    fn from(err: LexError) -> Self {
+        ParseError::LexError(err)
+    }
+}
+
 impl From<CommandError> for ParseError {
     fn from(cmd_err: CommandError) -> Self {
         ParseError::EvaluationError(cmd_err)
@@ -50,111 +90,871 @@ impl From<CommandError> for ParseError {
 // 🏠
 pub const HOME: &str = "~";
 
-pub fn parse_input(input: String, state: &mut State) -> Result<Cmd, ParseError> {
-    if input.is_empty() {
+// The built-ins whose `NAME=value` arguments are assignments.
+const DECLARATIONS: [&str; 5] = ["alias", "export", "local", "readonly", "set"];
+
+pub fn parse_input(input: String) -> Result<CmdList, ParseError> {
+    if input.trim().is_empty() {
         return Err(ParseError::InputEmpty);
     }
 
-    let expanded = parse_initial_cmd(&input, state)?;
-
-    let command = evaluate_cmd(expanded, state)?;
-    return Ok(command);
-}
-
-pub fn parse_initial_cmd(input: &str, state: &mut State) -> Result<String, ParseError> {
-    let initial_cmd: InitialCmd = match InitialCmdOrCommentParser::new().parse(&input) {
-        Ok(val) => match val {
-            InitialCmdOrComment::InitialCmd(v) => v,
-            InitialCmdOrComment::Comment => return Err(ParseError::Comment),
-        },
+    let command = match CommandListParser::new().parse(Lexer::new(&input)) {
+        Ok(val) => val,
+        // More lines are needed to complete the command, e.g. an `if` without its `fi` or an unclosed quote,
+        // the target of a redirect has to be on the same line.
+        Err(lalrpop_util::ParseError::UnrecognizedEOF { .. }) if !ends_with_redirect(&input) => {
+            return Err(ParseError::Incomplete)
+        }
+        Err(lalrpop_util::ParseError::User { error }) if is_unterminated(&error) => {
+            return Err(ParseError::Incomplete)
+        }
         Err(e) => {
             return Err(ParseError::LALRPopErr(
                 e.to_string(),
-                String::from("initial"),
+                String::from("command"),
             ))
         }
     };
 
-    Ok(expand_initial_cmd(initial_cmd, state)?)
+    if command.cmds.is_empty() {
+        return Err(ParseError::Comment);
+    }
+    Ok(command)
+}
+
+fn ends_with_redirect(input: &str) -> bool {
+    match Lexer::new(input).filter_map(Result::ok).last() {
+        Some((_, tok, _)) => tok.is_redirect(),
+        None => false,
+    }
+}
+
+fn is_unterminated(error: &LexError) -> bool {
+    match error {
+        LexError::UnterminatedQuote(_)
+        | LexError::UnterminatedSubstitution
+        | LexError::UnterminatedParameter
+        | LexError::UnterminatedHereDoc(_)
+        | LexError::TrailingBackslash => true,
+        LexError::MissingDelimiter => false,
+    }
+}
+
+/// Expands the command, arguments and redirects of the part,
+/// the first word after expansion is used as the command.
+pub fn expand_cmd_part(part: CmdPart, state: &mut State) -> Result<CmdPart, ParseError> {
+    let substitutions = state.substitutions.len();
+    let cmd = match state.aliases.get(&part.cmd) {
+        Some(alias) => alias.clone(),
+        None => part.cmd,
+    };
+
+    let mut words = expand_word(&expand_braces(&cmd), false, state)?;
+    let declaration =
+        matches!(words.first(), Some(w) if DECLARATIONS.contains(&w.to_string().as_str()));
+    for arg in part.args.into_iter() {
+        match arg {
+            Arg::Word(w) => words.append(&mut expand_word(&expand_braces(&w), declaration, state)?),
+            other => words.push(other),
+        }
+    }
+
+    let redirects = expand_redirects(part.redirects, state)?;
+
+    let mut env = vec![];
+    for (name, value) in part.env.into_iter() {
+        let value = expand_value(&value, state)?;
+        env.push((name, value));
+    }
+
+    // The pipes of process substitutions are closed when a program is executed unless they're passed on explicitly.
+    let passed = state.substitutions[substitutions..].iter().map(|s| {
+        let fd = s.pipe.as_raw_fd();
+        Redirect::Duplicate(fd, fd.to_string())
+    });
+    let redirects = passed.chain(redirects).collect();
+
+    let cmd = if words.is_empty() {
+        String::new()
+    } else {
+        words.remove(0).to_string()
+    };
+
+    Ok(CmdPart {
+        cmd,
+        args: words,
+        redirects,
+        env,
+    })
+}
+
+/// Expands the files of the redirects and the bodies of the here-documents.
+pub fn expand_redirects(
+    redirects: Vec<Redirect>,
+    state: &mut State,
+) -> Result<Vec<Redirect>, ParseError> {
+    let mut expanded = vec![];
+    for redirect in redirects.into_iter() {
+        expanded.push(match redirect {
+            Redirect::In(fd, f) => Redirect::In(fd, expand_single_word(&f, state)?),
+            Redirect::Out(fd, f) => Redirect::Out(fd, expand_single_word(&f, state)?),
+            Redirect::Clobber(fd, f) => Redirect::Clobber(fd, expand_single_word(&f, state)?),
+            Redirect::Append(fd, f) => Redirect::Append(fd, expand_single_word(&f, state)?),
+            Redirect::Duplicate(fd, t) => Redirect::Duplicate(fd, expand_single_word(&t, state)?),
+            Redirect::OutAll(f) => Redirect::OutAll(expand_single_word(&f, state)?),
+            Redirect::AppendAll(f) => Redirect::AppendAll(expand_single_word(&f, state)?),
+            Redirect::HereDoc(mut doc) => {
+                if doc.expand {
+                    doc.body = expand_text(&doc.body, state)?;
+                    doc.expand = false;
+                }
+                Redirect::HereDoc(doc)
+            }
+            Redirect::HereString(w) => Redirect::HereString(expand_value(&w, state)?),
+        });
+    }
+    Ok(expanded)
+}
+
+/// Expands the value of a variable assignment, the result is never split.
+pub fn expand_value(value: &str, state: &mut State) -> Result<String, ParseError> {
+    Ok(expand_pieces(value, state)?
+        .into_iter()
+        .map(|piece| match piece {
+            Piece::Unquoted(text) | Piece::Quoted(text) => text,
+            Piece::Split => String::from(" "),
+        })
+        .collect())
+}
+
+/// Expands the words of a `for` loop.
+pub fn expand_words(words: Vec<Arg>, state: &mut State) -> Result<Vec<String>, ParseError> {
+    let mut expanded = vec![];
+    for word in words.into_iter() {
+        match word {
+            Arg::Word(w) => expanded.append(&mut expand_word(&expand_braces(&w), false, state)?),
+            other => expanded.push(other),
+        }
+    }
+    Ok(expanded.iter().map(|a| a.to_string()).collect())
+}
+
+/// Replaces an argument containing an unquoted glob pattern with the sorted paths matching it,
+/// what happens when nothing matches depends on the `nullglob` and `failglob` options.
+/// The assignments given to a `declaration` built-in, e.g. `export a=*`, aren't globbed.
+fn expand_globs(field: Field, declaration: bool, state: &State) -> Result<Vec<Arg>, ParseError> {
+    if !field.glob || (declaration && field.is_assignment()) {
+        return Ok(vec![field.into_arg()]);
+    }
+
+    let paths = expand_glob(&field.pattern);
+    if !paths.is_empty() {
+        Ok(paths.into_iter().map(Arg::Word).collect())
+    } else if state.options.failglob {
+        Err(ParseError::NoMatch(field.text))
+    } else if state.options.nullglob {
+        Ok(vec![])
+    } else {
+        Ok(vec![field.into_arg()])
+    }
+}
+
+/// Expands a glob pattern, special characters in quoted strings are escaped to match literally.
+pub fn expand_pattern(pattern: &str, state: &mut State) -> Result<String, ParseError> {
+    Ok(expand_pieces(pattern, state)?
+        .into_iter()
+        .map(|piece| match piece {
+            Piece::Unquoted(text) => text,
+            Piece::Quoted(text) => escape_glob(&text),
+            Piece::Split => String::from(" "),
+        })
+        .collect())
+}
+
+fn escape_glob(s: &str) -> String {
+    let mut escaped = String::new();
+    for c in s.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
 }
 
-fn expand_initial_cmd(cmd: InitialCmd, state: &mut State) -> Result<String, ParseError> {
-    let mut text = "".to_string();
-    for part in cmd.parts.into_iter() {
-        match part {
-            InitialCmdPart::String(val) => {
-                text = text + val.as_str();
+fn expand_single_word(word: &str, state: &mut State) -> Result<String, ParseError> {
+    let mut words = expand_word(word, false, state)?;
+    match words.len() {
+        1 => Ok(words.remove(0).to_string()),
+        _ => Err(ParseError::AmbiguousRedirect(word.to_string())),
+    }
+}
+
+/// Expands the body of a here-document or the value of a prompt, only the `$` expansions are performed,
+/// quotes are kept as is and `\` only escapes `$`, `\` and newlines.
+pub fn expand_text(body: &str, state: &mut State) -> Result<String, ParseError> {
+    Ok(expand_dollars(body, false, state)?.0.join(" "))
+}
+
+/// Performs the `$` expansions in the text, up to the closing `"` if it's `quoted`,
+/// returns the expanded words and the length of the text that was expanded.
+/// `\` only escapes `$`, `\` and newlines, within double quotes also `` ` `` and `"`.
+/// Within double quotes `$@` starts a new word for each positional parameter,
+/// when there are none and the quotes are otherwise empty there are no words at all.
+fn expand_dollars(
+    text: &str,
+    quoted: bool,
+    state: &mut State,
+) -> Result<(Vec<String>, usize), ParseError> {
+    let special: &[char] = if quoted {
+        &['$', '\\', '"']
+    } else {
+        &['$', '\\']
+    };
+    let mut words = vec![];
+    let mut expanded = String::new();
+    let mut empty_at = false;
+    let mut rest = text;
+    while let Some(i) = rest.find(special) {
+        expanded += &rest[..i];
+        let mut chars = rest[i..].chars();
+        let (c, next) = (chars.next(), chars.next());
+        rest = &rest[i + 1..];
+        match (c, next) {
+            (Some('"'), _) => {
+                if !(empty_at && words.is_empty() && expanded.is_empty()) {
+                    words.push(expanded);
+                }
+                return Ok((words, text.len() - rest.len() - 1));
             }
-            InitialCmdPart::Calculation(cmd) => {
-                let inner = expand_initial_cmd(cmd, state)?;
-                let new_cmd = evaluate_cmd(inner, state)?;
-                text += handle_sub_command(new_cmd, state)?.as_str();
+            (Some('\\'), Some(next))
+                if matches!(next, '$' | '\\' | '\n') || (quoted && matches!(next, '`' | '"')) =>
+            {
+                if next != '\n' {
+                    expanded.push(next);
+                }
+                rest = &rest[1..];
             }
-            InitialCmdPart::SingleQuotedString(str) => {
-                text = text + str.as_str();
+            (Some('$'), _) if quoted && (rest.starts_with('@') || rest.starts_with("{@}")) => {
+                let mut params = state.positional_args.iter();
+                match params.next() {
+                    Some(first) => expanded += first,
+                    None => empty_at = true,
+                }
+                for param in params {
+                    words.push(std::mem::replace(&mut expanded, param.clone()));
+                }
+                rest = &rest[if rest.starts_with('@') { 1 } else { 3 }..];
             }
+            (Some('$'), _) => match expand_dollar(rest, state)? {
+                Some((value, len)) => {
+                    expanded += &value;
+                    rest = &rest[len..];
+                }
+                None => expanded.push('$'),
+            },
+            (Some(c), _) => expanded.push(c),
+            (None, _) => {}
         }
     }
+    if quoted {
+        return Err(ParseError::LexError(LexError::UnterminatedQuote('"')));
+    }
+    expanded += rest;
+    words.push(expanded);
+    Ok((words, text.len()))
+}
+
+/// Expands the `$( )`, `$(( ))`, `${ }` or variable following a `$` at the start of the text,
+/// returns the value and the length of the expansion or `None` if the `$` isn't followed by one.
+fn expand_dollar(text: &str, state: &mut State) -> Result<Option<(String, usize)>, ParseError> {
+    let next = match text.chars().next() {
+        Some(next) => next,
+        None => return Ok(None),
+    };
+    let expansion = match next {
+        '(' => {
+            let end = closing_paren(text).ok_or(LexError::UnterminatedSubstitution)?;
+            let inner = &text[1..end];
+            // `$(( ))` is an arithmetic expansion if the second `(` is closed right before the last `)`,
+            // otherwise it's a command substitution starting with a subshell, e.g. `$((cd /tmp); ls)`.
+            let arithmetic = text.starts_with("((") && closing_paren(inner) == Some(end - 2);
+            let value = match inner.strip_prefix('(').and_then(|e| e.strip_suffix(')')) {
+                Some(expression) if arithmetic => {
+                    let expression = expand_text(expression, state)?;
+                    evaluate_arithmetic(&expression, state)?.to_string()
+                }
+                _ => match parse_input(inner.to_string()) {
+                    Ok(cmd) => handle_sub_command(cmd, state)?,
+                    Err(ParseError::InputEmpty | ParseError::Comment) => String::new(),
+                    Err(e) => return Err(e),
+                },
+            };
+            (value, end + 1)
+        }
+        '{' => {
+            let end = closing_brace(text).ok_or(LexError::UnterminatedParameter)?;
+            (expand_parameter(&text[1..end], state)?, end + 1)
+        }
+        c if c.is_ascii_alphabetic() || c == '_' => {
+            let end = text
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(text.len());
+            (read_var(&text[..end], state), end)
+        }
+        c if c.is_ascii_digit() || "?!#@*$".contains(c) => (read_var(&text[..1], state), 1),
+        _ => return Ok(None),
+    };
+    Ok(Some(expansion))
+}
 
-    Ok(text)
+/// The index of the `)` closing the `(` at the start of the text, quoted and escaped parentheses are skipped.
+fn closing_paren(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut quote = None;
+    let mut chars = text.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match (c, quote) {
+            ('\\', Some('"') | None) => {
+                chars.next();
+            }
+            (_, Some(q)) if c == q => quote = None,
+            (_, Some(_)) => {}
+            ('\'' | '"', None) => quote = Some(c),
+            ('(', None) => depth += 1,
+            (')', None) => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
 }
 
-fn evaluate_cmd(cmd: String, state: &mut State) -> Result<Cmd, ParseError> {
-    let replaced = perform_replacements(cmd, state)?;
+/// The index of the `}` closing the `{` at the start of the text, quoted and escaped braces
+/// as well as those of nested expansions are skipped, e.g. in `${a:-${b}}` or `${v/x/\}}`.
+fn closing_brace(text: &str) -> Option<usize> {
+    let mut i = 1;
+    while let Some(c) = text[i..].chars().next() {
+        let after = &text[i + c.len_utf8()..];
+        let skipped = match c {
+            '}' => return Some(i),
+            '\\' => after.chars().next().map_or(0, char::len_utf8),
+            '\'' => after.find('\'')? + 1,
+            '"' => closing_quote(after)? + 1,
+            '$' if after.starts_with('{') => closing_brace(after)? + 1,
+            '$' if after.starts_with('(') => closing_paren(after)? + 1,
+            _ => 0,
+        };
+        i += c.len_utf8() + skipped;
+    }
+    None
+}
 
-    match CommandParser::new().parse(&replaced) {
-        Ok(val) => Ok(val),
-        Err(e) => Err(ParseError::LALRPopErr(
-            e.to_string(),
-            String::from("command"),
-        )),
+/// The index of the `"` closing a double quoted string, the opening `"` isn't part of the text.
+fn closing_quote(text: &str) -> Option<usize> {
+    let mut chars = text.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '"' => return Some(i),
+            _ => {}
+        }
     }
+    None
 }
 
-fn perform_replacements(str: String, state: &State) -> Result<String, ParseError> {
-    let replaced_cmd: ReplacementsCmd = match ReplacementCmdParser::new().parse(&str) {
-        Ok(val) => val,
-        Err(e) => {
-            return Err(ParseError::LALRPopErr(
-                e.to_string(),
-                String::from("replacements"),
-            ))
+/// Decodes the contents of a `$'...'` string up to the closing `'`, returns them and the index of the `'`.
+/// The escapes are those of C, e.g. `\n`, `\t`, `\x41` or `\101`, as well as `\e` and `\'`.
+/// The hexadecimal and octal escapes are single bytes, e.g. `\xc3\xa9` is `é`.
+fn decode_ansi_c(text: &str) -> Result<(String, usize), ParseError> {
+    let mut decoded = vec![];
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if c == '\'' {
+            return Ok((String::from_utf8_lossy(&decoded).into_owned(), i));
         }
-    };
-    Ok(handle_replaced_cmd(replaced_cmd, state))
-}
-
-fn handle_replaced_cmd(replacement_cmd: ReplacementsCmd, state: &State) -> String {
-    let mut replaced_str = String::new();
-    for part in replacement_cmd.parts.iter() {
-        let val = match part {
-            ReplacementPart::String(s) => s.to_string(),
-            ReplacementPart::Word(word) => word
-                .split(" ")
-                .map(|w| perform_replacement(w.to_string(), state))
-                .collect::<Vec<String>>()
-                .join(" "),
-            ReplacementPart::Variable(var) => read_var(var.to_string(), state).to_string(),
+        if c != '\\' {
+            decoded.extend(c.to_string().bytes());
+            continue;
+        }
+
+        let escaped = match chars.next() {
+            Some((_, escaped)) => escaped,
+            None => break,
         };
-        replaced_str = replaced_str + &val;
+        let byte = match escaped {
+            'n' => b'\n',
+            't' => b'\t',
+            'r' => b'\r',
+            'a' => 0x07,
+            'b' => 0x08,
+            'e' | 'E' => 0x1b,
+            'f' => 0x0c,
+            'v' => 0x0b,
+            '\\' | '\'' | '"' | '?' => escaped as u8,
+            'x' if matches!(chars.peek(), Some((_, c)) if c.is_ascii_hexdigit()) => {
+                read_digits(&mut chars, 16, 2, 0) as u8
+            }
+            // Only the lowest byte is kept, e.g. `\777` is `\xff`.
+            '0'..='7' => (read_digits(&mut chars, 8, 2, escaped as u32 - '0' as u32) & 0xff) as u8,
+            other => {
+                decoded.push(b'\\');
+                decoded.extend(other.to_string().bytes());
+                continue;
+            }
+        };
+        decoded.push(byte);
+    }
+    Err(ParseError::LexError(LexError::UnterminatedQuote('\'')))
+}
+
+/// Reads up to `max` digits of the radix, continuing the given value.
+fn read_digits(chars: &mut Peekable<CharIndices>, radix: u32, max: usize, mut value: u32) -> u32 {
+    for _ in 0..max {
+        match chars.peek().and_then(|(_, c)| c.to_digit(radix)) {
+            Some(digit) => value = value * radix + digit,
+            None => break,
+        }
+        chars.next();
+    }
+    value
+}
+
+/// A part of a word after its expansions, the parts of a word are joined into a single argument
+/// unless the unquoted parts contain whitespace.
+enum Piece {
+    /// Unquoted text, the results of expansions are split into several arguments at whitespace.
+    Unquoted(String),
+    /// The contents of a quoted string or an escaped character, used literally.
+    Quoted(String),
+    /// The boundary between two positional parameters of a quoted `$@`, which are separate arguments.
+    Split,
+}
+
+/// Performs the expansions of the word, keeping track of which parts of it were quoted.
+/// Single quotes keep everything as is, double quotes only allow the `$` expansions
+/// and `$'...'` strings are decoded.
+fn expand_pieces(word: &str, state: &mut State) -> Result<Vec<Piece>, ParseError> {
+    let mut pieces = vec![];
+    let mut rest = word;
+    while let Some(c) = rest.chars().next() {
+        let after = &rest[c.len_utf8()..];
+        rest = match c {
+            '\\' => match after.chars().next() {
+                Some(escaped) => {
+                    pieces.push(Piece::Quoted(escaped.to_string()));
+                    &after[escaped.len_utf8()..]
+                }
+                None => return Err(ParseError::LexError(LexError::TrailingBackslash)),
+            },
+            '\'' => {
+                let end = after.find('\'').ok_or(LexError::UnterminatedQuote('\''))?;
+                pieces.push(Piece::Quoted(after[..end].to_string()));
+                &after[end + 1..]
+            }
+            '"' => {
+                let (words, end) = expand_dollars(after, true, state)?;
+                for (i, word) in words.into_iter().enumerate() {
+                    if i > 0 {
+                        pieces.push(Piece::Split);
+                    }
+                    pieces.push(Piece::Quoted(word));
+                }
+                &after[end + 1..]
+            }
+            '$' if after.starts_with('\'') => {
+                let (content, end) = decode_ansi_c(&after[1..])?;
+                pieces.push(Piece::Quoted(content));
+                &after[end + 2..]
+            }
+            '$' => match expand_dollar(after, state)? {
+                Some((value, len)) => {
+                    pieces.push(Piece::Unquoted(value));
+                    &after[len..]
+                }
+                None => {
+                    pieces.push(Piece::Unquoted(String::from("$")));
+                    after
+                }
+            },
+            '<' | '>' if after.starts_with('(') => {
+                let end = closing_paren(after).ok_or(LexError::UnterminatedSubstitution)?;
+                let path = substitute_process(&after[1..end], c == '<', state)?;
+                pieces.push(Piece::Unquoted(path));
+                &after[end + 1..]
+            }
+            _ => {
+                let end = after
+                    .find(['\\', '\'', '"', '$', '<', '>'])
+                    .map_or(rest.len(), |i| i + c.len_utf8());
+                pieces.push(Piece::Unquoted(perform_replacement(&rest[..end], state)));
+                &rest[end..]
+            }
+        };
+    }
+    Ok(pieces)
+}
+
+fn perform_replacement(str: &str, state: &State) -> String {
+    str.replace(HOME, state.home.as_str())
+}
+
+/// An argument being built from the pieces of a word.
+#[derive(Default)]
+struct Field {
+    text: String,
+    // The text as a glob pattern, where the quoted characters match literally.
+    pattern: String,
+    quoted: bool,
+    // Whether any of the unquoted characters are special in a glob.
+    glob: bool,
+    // The index of the first `=`, if it's unquoted and comes before any quoted parts.
+    equals: Option<usize>,
+}
+
+impl Field {
+    fn push(&mut self, c: char, quoted: bool) {
+        if c == '=' && !quoted && !self.quoted && self.equals.is_none() {
+            self.equals = Some(self.text.len());
+        }
+        self.text.push(c);
+        if quoted {
+            self.pattern += &escape_glob(&c.to_string());
+        } else {
+            self.pattern.push(c);
+            self.glob |= matches!(c, '*' | '?' | '[');
+        }
+        self.quoted |= quoted;
+    }
+
+    /// Whether the field has the form `NAME=value`.
+    fn is_assignment(&self) -> bool {
+        matches!(self.equals, Some(i) if is_valid_identifier(&self.text[..i]))
+    }
+
+    fn into_arg(self) -> Arg {
+        match self.equals {
+            Some(i) => {
+                let value = self.text[i + 1..].to_string();
+                let value = match self.quoted {
+                    true => Assignment::String(value),
+                    false => Assignment::Word(value),
+                };
+                Arg::Assignment(self.text[..i].to_string(), value)
+            }
+            None if self.quoted => Arg::String(self.text),
+            None => Arg::Word(self.text),
+        }
     }
-    replaced_str
 }
 
-fn perform_replacement(str: String, state: &State) -> String {
-    let mut replaced = str.clone();
-    for (key, val) in state.aliases.iter() {
-        if &str == key {
-            replaced = val.clone();
-            break;
+/// Expands the word, the unquoted parts of the result are split into several arguments at whitespace
+/// and the arguments containing unquoted glob patterns are replaced by the paths matching them.
+fn expand_word(word: &str, declaration: bool, state: &mut State) -> Result<Vec<Arg>, ParseError> {
+    let mut fields = vec![];
+    let mut field: Option<Field> = None;
+    for piece in expand_pieces(word, state)?.into_iter() {
+        match piece {
+            Piece::Unquoted(text) => {
+                for c in text.chars() {
+                    if c.is_whitespace() {
+                        fields.extend(field.take());
+                    } else {
+                        field.get_or_insert_with(Field::default).push(c, false);
+                    }
+                }
+            }
+            Piece::Split => fields.extend(field.take()),
+            Piece::Quoted(text) => {
+                // An empty string is still an argument, e.g. `""`.
+                let quoted = field.get_or_insert_with(Field::default);
+                quoted.quoted = true;
+                for c in text.chars() {
+                    quoted.push(c, true);
+                }
+            }
         }
     }
-    replaced.replace(HOME, state.home.as_str())
+    fields.extend(field.take());
+
+    let mut args = vec![];
+    for field in fields.into_iter() {
+        args.append(&mut expand_globs(field, declaration, state)?);
+    }
+    Ok(args)
+}
+
+/// Starts the command of a `<(cmd)` (`read`) or `>(cmd)` and returns the path connected to it.
+fn substitute_process(cmd: &str, read: bool, state: &mut State) -> Result<String, ParseError> {
+    match parse_input(cmd.to_string()) {
+        Ok(cmd) => Ok(handle_process_substitution(cmd, read, state)?),
+        Err(ParseError::InputEmpty | ParseError::Comment) => Ok(String::new()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Evaluates the (already expanded) expression of an arithmetic expansion, an empty expression is 0.
+fn evaluate_arithmetic(expression: &str, state: &mut State) -> Result<i64, ParseError> {
+    if expression.trim().is_empty() {
+        return Ok(0);
+    }
+
+    match ArithmeticParser::new().parse(expression) {
+        Ok(expr) => Ok(evaluate(&expr, state)?),
+        Err(e) => Err(ParseError::LALRPopErr(
+            e.to_string(),
+            String::from("arithmetic"),
+        )),
+    }
 }
 
-fn read_var(var: String, state: &State) -> &str {
-    match state.variables.get(&var) {
-        Some(val) => val,
-        None => "",
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state_with_args(args: &[&str]) -> State {
+        let mut state = State::for_tests();
+        state.positional_args = args.iter().map(|a| a.to_string()).collect();
+        state
+    }
+
+    fn words(word: &str, state: &mut State) -> Vec<String> {
+        expand_word(word, false, state)
+            .unwrap_or_else(|e| panic!("{}", e))
+            .iter()
+            .map(|a| a.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn lists_are_split_at_separators() {
+        let command = parse_input(String::from("echo a; echo b\n\necho c;"))
+            .unwrap_or_else(|e| panic!("{}", e));
+        assert_eq!(command.cmds.len(), 3);
+        let command = parse_input(String::from("echo 'a;b\nc'; echo \"d;\""))
+            .unwrap_or_else(|e| panic!("{}", e));
+        assert_eq!(command.cmds.len(), 2);
+        assert!(matches!(
+            parse_input(String::from("# a; b")),
+            Err(ParseError::Comment)
+        ));
+    }
+
+    #[test]
+    fn quotes_are_removed_when_expanding() {
+        let mut state = State::for_tests();
+        assert_eq!(words("'a;b'", &mut state), vec!["a;b"]);
+        assert_eq!(words("\"c d\"", &mut state), vec!["c d"]);
+    }
+
+    #[test]
+    fn dollar_question_is_the_exit_status() {
+        let mut state = State::for_tests();
+        state.exit_status = 3;
+        assert_eq!(words("$?", &mut state), vec!["3"]);
+        assert_eq!(words("\"$?\"", &mut state), vec!["3"]);
+    }
+
+    #[test]
+    fn unfinished_if_is_incomplete() {
+        for input in [
+            "if true; then echo a",
+            "if true\nthen\necho a\nelse",
+            "if true",
+        ] {
+            assert!(
+                matches!(
+                    parse_input(String::from(input)),
+                    Err(ParseError::Incomplete)
+                ),
+                "{}",
+                input
+            );
+        }
+        assert!(parse_input(String::from("if true; then echo a; fi")).is_ok());
+    }
+
+    #[test]
+    fn positional_parameters() {
+        let mut state = State::for_tests();
+        state.positional_args = vec![String::from("a"), String::from("b")];
+        assert_eq!(words("$1", &mut state), vec!["a"]);
+        assert_eq!(words("$#", &mut state), vec!["2"]);
+        assert_eq!(words("$2$1", &mut state), vec!["ba"]);
+        assert_eq!(words("-$3-", &mut state), vec!["--"]);
+    }
+
+    #[test]
+    fn braces_are_expanded_into_words() {
+        let mut state = State::for_tests();
+        assert_eq!(
+            words(&expand_braces("a{1,2}"), &mut state),
+            vec!["a1", "a2"]
+        );
+        assert_eq!(
+            words(&expand_braces("{1..3}"), &mut state),
+            vec!["1", "2", "3"]
+        );
+        assert_eq!(words(&expand_braces("'{1,2}'"), &mut state), vec!["{1,2}"]);
+    }
+
+    #[test]
+    fn arithmetic_is_expanded_in_words() {
+        let mut state = State::for_tests();
+        assert_eq!(words("$((1 + 2 * 3))", &mut state), vec!["7"]);
+        assert_eq!(words("$((x = 4))$((x * 2))", &mut state), vec!["48"]);
+    }
+
+    #[test]
+    fn subshell_at_the_start_of_a_substitution_is_not_arithmetic() {
+        let mut state = State::for_tests();
+        assert_eq!(words("$((echo x); echo y)", &mut state), vec!["x", "y"]);
+        assert_eq!(words("$(( (1 + 2) * 3 ))", &mut state), vec!["9"]);
+    }
+
+    #[test]
+    fn variable_names_are_as_long_as_possible() {
+        let mut state = State::for_tests();
+        state
+            .variables
+            .insert(String::from("name"), String::from("v"));
+        assert_eq!(words("$name.x", &mut state), vec!["v.x"]);
+        assert_eq!(words("${name}x", &mut state), vec!["vx"]);
+        assert_eq!(words("-$namex-", &mut state), vec!["--"]);
+        assert_eq!(words("${name:-d}${unset_name:-d}", &mut state), vec!["vd"]);
+    }
+
+    #[test]
+    fn parameters_end_at_the_matching_brace() {
+        let mut state = State::for_tests();
+        state
+            .variables
+            .insert(String::from("name"), String::from("v"));
+        assert_eq!(words("${unset_name:-${name}}x", &mut state), vec!["vx"]);
+        assert_eq!(words("\"${unset_name:-a\\}b}\"", &mut state), vec!["a}b"]);
+    }
+
+    #[test]
+    fn open_constructs_are_incomplete() {
+        for input in [
+            "echo 'a",
+            "echo \"a",
+            "echo a |",
+            "echo a &&",
+            "echo a ||",
+            "echo $(ls",
+            "echo ${a",
+            "echo a \\",
+            "while true; do",
+            "for i in a b; do",
+            "case a in",
+            "{ echo a",
+            "cat <<EOF\nbody",
+        ] {
+            assert!(
+                matches!(
+                    parse_input(String::from(input)),
+                    Err(ParseError::Incomplete)
+                ),
+                "{}",
+                input
+            );
+        }
+        assert!(parse_input(String::from("echo 'a\nb' |\ncat")).is_ok());
+    }
+
+    #[test]
+    fn redirect_without_target_is_a_syntax_error() {
+        for input in ["echo >", "echo 2>&", "cat <", "echo a 2>"] {
+            assert!(
+                matches!(
+                    parse_input(String::from(input)),
+                    Err(ParseError::LALRPopErr(..))
+                ),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn backslashes_escape_outside_of_quotes() {
+        let mut state = State::for_tests();
+        assert_eq!(words("\\\"hi\\\"", &mut state), vec!["\"hi\""]);
+        assert_eq!(
+            words("file\\ with\\ spaces", &mut state),
+            vec!["file with spaces"]
+        );
+        assert_eq!(words("\\$HOME\\\\", &mut state), vec!["$HOME\\"]);
+    }
+
+    #[test]
+    fn quoting_rules_inside_quotes() {
+        let mut state = State::for_tests();
+        assert_eq!(
+            words("\"say \\\"hi\\\" \\$x \\a\"", &mut state),
+            vec!["say \"hi\" $x \\a"]
+        );
+        assert_eq!(words("'a\\b $x'", &mut state), vec!["a\\b $x"]);
+        assert_eq!(
+            words("$'a\\tb\\x41\\101\\n\\''", &mut state),
+            vec!["a\tbAA\n'"]
+        );
+        assert_eq!(words("$'\\xc3\\xa9\\303\\251'", &mut state), vec!["éé"]);
+        assert_eq!(words("$'\\777'", &mut state), vec!["\u{fffd}"]);
+        assert_eq!(words("$'\\q'", &mut state), vec!["\\q"]);
+    }
+
+    #[test]
+    fn touching_segments_are_one_word() {
+        let mut state = State::for_tests();
+        state
+            .variables
+            .insert(String::from("v"), String::from("a b"));
+        assert_eq!(words("--n=\"$v\"_x'y'", &mut state), vec!["--n=a b_xy"]);
+        assert_eq!(words("\"a\"\"b\"c", &mut state), vec!["abc"]);
+        assert_eq!(words("$v\"c\"", &mut state), vec!["a", "bc"]);
+    }
+
+    #[test]
+    fn quoted_at_is_one_argument_per_parameter() {
+        let mut state = state_with_args(&["a b", "c"]);
+        assert_eq!(words("\"$@\"", &mut state), vec!["a b", "c"]);
+        assert_eq!(words("\"${@}\"", &mut state), vec!["a b", "c"]);
+        assert_eq!(words("x\"<$@>\"y", &mut state), vec!["x<a b", "c>y"]);
+    }
+
+    #[test]
+    fn quoted_at_without_parameters_is_no_argument() {
+        let mut state = state_with_args(&[]);
+        assert!(words("\"$@\"", &mut state).is_empty());
+        assert_eq!(words("\"x$@\"", &mut state), vec!["x"]);
+        assert_eq!(words("\"\"", &mut state), vec![""]);
+    }
+
+    #[test]
+    fn unquoted_and_star_are_split_at_whitespace() {
+        let mut state = state_with_args(&["a b", "c"]);
+        assert_eq!(words("$@", &mut state), vec!["a", "b", "c"]);
+        assert_eq!(words("\"$*\"", &mut state), vec!["a b c"]);
+    }
+
+    #[test]
+    fn quoted_at_is_joined_in_values() {
+        let mut state = state_with_args(&["a b", "c"]);
+        assert_eq!(
+            expand_value("\"$@\"", &mut state).unwrap_or_else(|e| panic!("{}", e)),
+            "a b c"
+        );
+        assert_eq!(
+            expand_text("$@ \"$@\"\n", &mut state).unwrap_or_else(|e| panic!("{}", e)),
+            "a b c \"a b c\"\n"
+        );
     }
 }
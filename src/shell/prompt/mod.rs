@@ -1,3 +1,4 @@
+#[allow(clippy::module_inception)]
 pub mod prompt;
 pub mod prompt_types;
 pub mod modules;
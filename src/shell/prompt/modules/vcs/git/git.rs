@@ -87,7 +87,7 @@ fn get_branch_info(repo: &Repository) -> Result<String, GitError> {
         }
     };
 
-    if branch_info.is_empty() == false {
+    if !branch_info.is_empty() {
         return Ok(format!("{}on {}", fg_color(Color::Orange), branch_info));
     }
 
@@ -102,7 +102,7 @@ fn github_string(branch: &Branch, repo: &Repository) -> Result<String, GitError>
     .name()?
     {
         None => "",
-        Some(upstream_name) => match upstream_name.split("/").into_iter().next() {
+        Some(upstream_name) => match upstream_name.split("/").next() {
             None => "",
             Some(remote_name) => {
                 let remote = repo.find_remote(remote_name)?;
@@ -198,17 +198,17 @@ fn get_status_info(repo: &Repository) -> Result<String, GitError> {
 }
 
 fn is_modified(status: Status) -> bool {
-    return status.is_wt_deleted()
+    status.is_wt_deleted()
         || status.is_wt_modified()
         || status.is_wt_renamed()
         || status.is_wt_typechange()
-        || status.is_wt_new();
+        || status.is_wt_new()
 }
 
 fn is_staged(status: Status) -> bool {
-    return status.is_index_deleted()
+    status.is_index_deleted()
         || status.is_index_modified()
         || status.is_index_new()
         || status.is_index_renamed()
-        || status.is_index_typechange();
+        || status.is_index_typechange()
 }
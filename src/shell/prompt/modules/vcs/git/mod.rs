@@ -1 +1,2 @@
+#[allow(clippy::module_inception)]
 pub mod git;
\ No newline at end of file
@@ -2,7 +2,7 @@ use crate::shell::common::colors::{
     bg_color, bg_color_code, fg_color, fg_color_code, reset_color, Color, ColorError,
 };
 use crate::shell::common::state::State;
-use crate::shell::parse_command::{parse_initial_cmd, ParseError, HOME};
+use crate::shell::parse_command::{expand_text, ParseError, HOME};
 use crate::shell::prompt::modules::vcs::git::git::{get_git_prompt, GitError};
 use crate::shell::prompt::prompt_types::{Argument, PromptCmd, PromptCmdPart, PromptEscape};
 use crate::shell::rl_helper::RLHelper;
@@ -18,6 +18,7 @@ use crate::prompt::PromptCmdParser;
 
 pub enum PromptError {
     Ignore,
+    Interrupted,
     RLError(ReadlineError),
     LalrpopError(String),
     ParseError(ParseError),
@@ -38,6 +39,7 @@ impl Display for PromptError {
             PromptError::ColorError(e) => write!(f, "color error: {}", e),
             PromptError::GitError(e) => write!(f, "git error: {}", e),
             PromptError::Ignore => write!(f, "ignored error"),
+            PromptError::Interrupted => write!(f, "interrupted"),
         }
     }
 }
@@ -75,29 +77,57 @@ pub fn read_input(rl: &mut Editor<RLHelper>, state: &mut State) -> Result<String
         }
     };
     let input = match rl.readline(prompt.as_str()) {
-        Ok(val) => val,
+        Ok(val) => without_newline(val),
         Err(e) => {
             return match e {
-                ReadlineError::Interrupted => Err(PromptError::Ignore),
+                ReadlineError::Interrupted => Err(PromptError::Interrupted),
                 ReadlineError::Eof => Err(PromptError::Ignore),
                 _ => Err(PromptError::RLError(e)),
             }
         }
     };
-    rl.add_history_entry(input.clone());
     Ok(input)
 }
 
-fn get_prompt(state: &mut State) -> Result<String, PromptError> {
-    if let Some(p) = state.variables.get("PROMPT") {
-        let expanded = match parse_initial_cmd(&p.clone(), state) {
+// 🔗 Used when `PS2` isn't set.
+const CONTINUATION_PROMPT: &str = "> ";
+
+/// Reads another line of a command that continues over several lines, prompted for with `PS2`.
+/// Only needed when rustyline doesn't support the terminal, it otherwise reads all the lines at once.
+pub fn read_continuation(
+    rl: &mut Editor<RLHelper>,
+    state: &mut State,
+) -> Result<String, PromptError> {
+    let prompt = match state.get_var("PS2") {
+        Some(p) => match expand_prompt(&p, state) {
             Ok(v) => v,
-            Err(ParseError::Comment | ParseError::InputEmpty) => "".to_string(),
-            Err(e) => return Err(PromptError::from(e)),
-        };
+            Err(e) => {
+                println!("vrsh: failed to parse PS2: {}", e);
+                CONTINUATION_PROMPT.to_string()
+            }
+        },
+        None => CONTINUATION_PROMPT.to_string(),
+    };
+    match rl.readline(prompt.as_str()) {
+        Ok(val) => Ok(without_newline(val)),
+        Err(ReadlineError::Interrupted) => Err(PromptError::Interrupted),
+        Err(ReadlineError::Eof) => Err(PromptError::Ignore),
+        Err(e) => Err(PromptError::RLError(e)),
+    }
+}
 
-        let expanded_prompt = prompt_expand(&expanded, state)?;
-        return Ok(expanded_prompt);
+/// Lines read from a terminal rustyline doesn't support keep their newline,
+/// it would otherwise end up in the bodies of here-documents.
+fn without_newline(mut line: String) -> String {
+    if line.ends_with('\n') {
+        line.pop();
+    }
+    line
+}
+
+fn get_prompt(state: &mut State) -> Result<String, PromptError> {
+    if let Some(p) = state.variables.get("PROMPT") {
+        return expand_prompt(&p.clone(), state);
     }
 
     let curr_dir = current_dir()?;
@@ -108,7 +138,13 @@ fn get_prompt(state: &mut State) -> Result<String, PromptError> {
 
     let prompt = wd.replace(state.home.as_str(), HOME);
 
-    return Ok(format!("{} > ", prompt));
+    Ok(format!("{} > ", prompt))
+}
+
+/// Expands the value of a prompt variable, both the `$` expansions and the prompt escapes.
+fn expand_prompt(prompt: &str, state: &mut State) -> Result<String, PromptError> {
+    let expanded = expand_text(prompt, state)?;
+    prompt_expand(&expanded, state)
 }
 
 fn prompt_expand(input: &str, state: &mut State) -> Result<String, PromptError> {
@@ -149,13 +185,13 @@ fn handle_prompt_escape(e: PromptEscape, state: &mut State) -> Result<String, Pr
                 Some(dir) => dir,
                 None => return Err(PromptError::NoWorkingDir),
             };
-            cwd.replace(state.home.as_str(), &format!("{}", HOME)) // 🏠
+            cwd.replace(state.home.as_str(), HOME) // 🏠
         }
         PromptEscape::CwdHomeParents => {
             let curr_dir = current_dir()?;
             let full_path = curr_dir.to_str().ok_or(PromptError::NoWorkingDir)?;
             if full_path == state.home.as_str() {
-                format!("")
+                String::new()
             } else {
                 let cwd = curr_dir
                     .as_path()
@@ -165,8 +201,8 @@ fn handle_prompt_escape(e: PromptEscape, state: &mut State) -> Result<String, Pr
                     .ok_or(PromptError::NoWorkingDir)?;
 
                 match cwd {
-                    "/" => format!(""),
-                    cwd => cwd.replace(state.home.as_str(), &format!("{}", HOME)), // 🏠
+                    "/" => String::new(),
+                    cwd => cwd.replace(state.home.as_str(), HOME), // 🏠
                 }
             }
         }
@@ -180,7 +216,7 @@ fn handle_prompt_escape(e: PromptEscape, state: &mut State) -> Result<String, Pr
                     Some(os_str) => {
                         format!("/{}", os_str.to_str().ok_or(PromptError::NoWorkingDir)?)
                     }
-                    None => format!("/"),
+                    None => "/".to_string(),
                 }
             }
         }
@@ -195,5 +231,6 @@ fn handle_prompt_escape(e: PromptEscape, state: &mut State) -> Result<String, Pr
         },
         PromptEscape::BGColorEnd => reset_color(),
         PromptEscape::Git => get_git_prompt()?,
+        PromptEscape::ExitStatus => state.exit_status.to_string(),
     })
 }
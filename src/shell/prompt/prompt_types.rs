@@ -17,6 +17,7 @@ pub enum PromptEscape {
     BGColorStart(Argument),
     BGColorEnd,
     Git,
+    ExitStatus, // The exit status of the last command
 }
 
 pub enum Argument {
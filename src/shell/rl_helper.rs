@@ -1,10 +1,11 @@
 use crate::shell::colors;
 use crate::shell::common::colors::{fg_color, Color};
+use crate::shell::parse_command::{parse_input, ParseError};
 use rustyline::completion::{Completer, FilenameCompleter, Pair};
 use rustyline::error::ReadlineError;
 use rustyline::highlight::{Highlighter, MatchingBracketHighlighter};
 use rustyline::hint::{Hinter, HistoryHinter};
-use rustyline::validate::Validator;
+use rustyline::validate::{ValidationContext, ValidationResult, Validator};
 use rustyline::Context;
 use rustyline_derive::Helper;
 use std::borrow::Cow;
@@ -15,10 +16,18 @@ pub struct RLHelper {
     pub completer: FilenameCompleter,
     pub hinter: HistoryHinter,
     pub highlighter: MatchingBracketHighlighter,
-    pub colored_prompt: String,
 }
 
-impl Validator for RLHelper {}
+impl Validator for RLHelper {
+    /// Pressing enter on a command that isn't complete yet, e.g. an `if` without its `fi`
+    /// or an unclosed quote, starts a new line of the command instead of running it.
+    fn validate(&self, ctx: &mut ValidationContext) -> Result<ValidationResult, ReadlineError> {
+        match parse_input(ctx.input().to_string()) {
+            Err(ParseError::Incomplete) => Ok(ValidationResult::Incomplete),
+            _ => Ok(ValidationResult::Valid(None)),
+        }
+    }
+}
 
 impl Completer for RLHelper {
     type Candidate = Pair;